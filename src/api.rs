@@ -0,0 +1,159 @@
+//! Read-only JSON API over the stored items and their covers, served by `serve` and by
+//! `neodb` while it crawls.
+
+use crate::{
+    config::Config,
+    download::{ivec_to_u32, u32_to_ivec, Trees, Web},
+    extract::{Album, Book, Movie},
+    ratings::history,
+    schema::decode,
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::get,
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sled::{Db, IVec};
+use std::net::SocketAddr;
+use tokio::task::JoinHandle;
+use tower_http::services::ServeDir;
+use tracing::{error, info};
+
+pub const DEFAULT_ADDR: &str = "127.0.0.1:3000";
+const DEFAULT_LIMIT: usize = 50;
+const MAX_LIMIT: usize = 500;
+
+/// routes of `T` under `/{KIND}s`, on its trees opened once
+fn kind_router<T: Web + Serialize + 'static>(db: &Db) -> Router {
+    let path = format!("/{}s", T::KIND);
+    Router::new()
+        .route(&path, get(list::<T>))
+        .route(&format!("{path}/:id"), get(one::<T>))
+        .route(&format!("{path}/:id/ratings"), get(ratings))
+        .with_state(Trees::open::<T>(db))
+}
+
+/// the api of `db`, with the covers of the cover paths of `config`
+pub fn router(db: &Db, config: &Config) -> Router {
+    Router::new()
+        .merge(kind_router::<Movie>(db))
+        .merge(kind_router::<Book>(db))
+        .merge(kind_router::<Album>(db))
+        .nest_service("/covers/movies", ServeDir::new(&config.movie_cover_path))
+        .nest_service("/covers/books", ServeDir::new(&config.book_cover_path))
+        .nest_service("/covers/albums", ServeDir::new(&config.album_cover_path))
+}
+
+/// Serve [router] on `addr` in the background, returning the bound address.
+pub fn spawn(db: &Db, config: &Config, addr: &str) -> Result<(SocketAddr, JoinHandle<()>), String> {
+    let addr: SocketAddr = addr
+        .parse()
+        .map_err(|e| format!("api address {addr}: {e}"))?;
+    let server = axum::Server::try_bind(&addr).map_err(|e| format!("api on {addr}: {e}"))?;
+    let server = server.serve(router(db, config).into_make_service());
+    let addr = server.local_addr();
+    info!("api on http://{addr}");
+    let handle = tokio::spawn(async move {
+        if let Err(e) = server.await {
+            error!("api: {e}");
+        }
+    });
+    Ok((addr, handle))
+}
+
+/// The item as json, with its `id` and the `cover_url` of the downloaded cover, if any.
+fn to_json<T: Web + Serialize>(trees: &Trees, id: u32, v: &IVec) -> Option<Value> {
+    let one: T = decode(v)?;
+    let mut value = serde_json::to_value(one).ok()?;
+    let cover_url = if trees.covers.contains_key(u32_to_ivec(id)).unwrap() {
+        value["cover"]
+            .as_str()
+            .and_then(|cover| cover.rsplit_once('.'))
+            .map(|(_, ext)| format!("/covers/{}s/{id}.{ext}", T::KIND))
+    } else {
+        None
+    };
+    value["id"] = json!(id);
+    value["cover_url"] = json!(cover_url);
+    Some(value)
+}
+
+async fn one<T: Web + Serialize>(
+    State(trees): State<Trees>,
+    Path(id): Path<u32>,
+) -> Result<Json<Value>, StatusCode> {
+    let v = trees
+        .data
+        .get(u32_to_ivec(id))
+        .unwrap()
+        .ok_or(StatusCode::NOT_FOUND)?;
+    to_json::<T>(&trees, id, &v)
+        .map(Json)
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// The recorded ratings of the item, oldest first, each with its `at` unix time.
+async fn ratings(
+    State(trees): State<Trees>,
+    Path(id): Path<u32>,
+) -> Result<Json<Vec<Value>>, StatusCode> {
+    if !trees.data.contains_key(u32_to_ivec(id)).unwrap() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let history = history(&trees.ratings, id)
+        .into_iter()
+        .map(|(at, rating)| {
+            let mut value = json!(rating);
+            value["at"] = json!(at);
+            value
+        })
+        .collect();
+    Ok(Json(history))
+}
+
+#[derive(Deserialize)]
+struct Page {
+    /// only ids greater than this
+    after: Option<u32>,
+    limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct List {
+    items: Vec<Value>,
+    /// `after` of the next page, `None` on the last page
+    next: Option<u32>,
+}
+
+/// Items in id order, paginated by `?after={id}&limit={n}`.
+async fn list<T: Web + Serialize>(
+    State(trees): State<Trees>,
+    Query(page): Query<Page>,
+) -> Json<List> {
+    let limit = page.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let from = page.after.map_or(0, |id| id.saturating_add(1));
+
+    let mut items = Vec::with_capacity(limit);
+    let mut last = None;
+    let mut more = false;
+    for kv in trees.data.range(u32_to_ivec(from)..) {
+        if items.len() == limit {
+            more = true;
+            break;
+        }
+        let (k, v) = kv.unwrap();
+        let id = ivec_to_u32(&k);
+        if let Some(value) = to_json::<T>(&trees, id, &v) {
+            items.push(value);
+        }
+        last = Some(id);
+    }
+
+    Json(List {
+        items,
+        next: if more { last } else { None },
+    })
+}
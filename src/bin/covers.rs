@@ -1,32 +1,56 @@
-use std::{
-    collections::HashMap,
-    fs::{self, File},
-    io::{copy, BufRead, BufReader},
-    path::PathBuf,
-    sync::Arc,
-};
+use std::{fs, path::PathBuf, sync::Arc, time::Duration};
 
-use datura::download::CLIENT;
+use datura::{
+    download::{fetch, save_body, Outcome, SaveError, CLIENT, DEFAULT_MAX_FILE_SIZE},
+    metrics::{self, METRICS},
+    mirror::{by_dest_name, claim_names, read_manifest, Entry, State},
+    progress::Progress,
+};
 use tokio::sync::Semaphore;
+use tracing::{error, info};
+use tracing_subscriber::{prelude::__tracing_subscriber_SubscriberExt, util::SubscriberInitExt};
+
+/// check the file against the entry off the async workers, as it reads the whole file
+async fn verify(entry: Entry, fpath: PathBuf) -> Result<(), String> {
+    tokio::task::spawn_blocking(move || entry.verify(&fpath))
+        .await
+        .unwrap()
+}
 
 #[tokio::main]
 async fn main() {
-    // read img urls
-    let mut args = std::env::args();
-    let fpath = args.nth(1).expect("file path not found");
-    let file = File::open(fpath).unwrap();
-    let r = BufReader::new(file);
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::EnvFilter::new("info"))
+        .with(tracing_subscriber::fmt::layer())
+        .init();
 
-    let mut urls = HashMap::new();
-    for i in r.lines() {
-        let i = i.unwrap();
-        if !i.is_empty() {
-            let (_, fname) = i.rsplit_once('/').unwrap();
-            urls.insert(fname.to_owned(), i);
+    let (flags, mut args): (Vec<String>, Vec<String>) = std::env::args()
+        .skip(1)
+        .partition(|arg| arg.starts_with("--"));
+    let bar = flags.iter().any(|f| f == "--progress-bar");
+    if let Some(addr) = flags.iter().find_map(|f| f.strip_prefix("--metrics=")) {
+        if let Err(e) = metrics::serve(addr) {
+            error!("{e}, running without metrics");
         }
     }
+    let max_size = flags
+        .iter()
+        .find_map(|f| f.strip_prefix("--max-size="))
+        .map(|s| s.parse().expect("max file size should be bytes"))
+        .unwrap_or(DEFAULT_MAX_FILE_SIZE);
+    args.reverse();
 
-    let out_path = args.next().expect("file path not found");
+    // read the manifest
+    let fpath = args.pop().expect("file path not found");
+    let mut entries = match read_manifest(&fpath) {
+        Ok(entries) => by_dest_name(entries),
+        Err(e) => {
+            error!("{e}");
+            return;
+        }
+    };
+
+    let out_path = args.pop().expect("file path not found");
     let dir = PathBuf::from(&out_path);
     let out_path = Arc::new(out_path);
 
@@ -34,65 +58,88 @@ async fn main() {
         fs::create_dir_all(&dir).unwrap();
     }
 
-    // skip the downloaded urls
-    dbg!(urls.len());
-    urls.retain(|k, _| !&dir.join(k).is_file());
-    dbg!(urls.len());
+    // if state db is not provided, it will be next to the output dir
+    let db_url = args.pop().unwrap_or_else(|| format!("{out_path}.db"));
+    let config = sled::Config::default().path(&db_url);
+    let db = config.open().unwrap();
+    let state_tree = db.open_tree("state").unwrap();
+    claim_names(&mut entries, &db.open_tree("urls").unwrap(), &state_tree);
 
-    let mut handers = Vec::with_capacity(urls.len());
-    let semaphore = Arc::new(Semaphore::new(500));
+    // skip the downloaded and permanently failed files
+    let all = entries.len();
+    entries.retain(|k, _| State::wanted(&state_tree, k, dir.join(k).exists(), max_size));
+    info!("to be gotten = {} of {}", entries.len(), all);
+
+    let progress = Progress::new("covers", entries.len(), bar);
+    progress.spawn_reporter(Duration::from_secs(10));
 
-    for i in urls.into_values() {
-        if !i.is_empty() {
-            let permit = semaphore.clone().acquire_owned().await.unwrap();
-            let out_path = out_path.clone();
-            let h = tokio::spawn(async move {
-                println!("{}", &i);
+    let mut handers = Vec::with_capacity(entries.len());
+    let semaphore = Arc::new(Semaphore::new(500));
+    METRICS.watch_semaphore("covers", &semaphore, 500);
 
-                let mut response = CLIENT.get(&i).send().await;
-                let mut cnt = 0;
-                while response.is_err() {
-                    println!("{:?}", response);
-                    response = CLIENT.get(&i).send().await;
-                    cnt += 1;
-                    if cnt >= 2 {
-                        break;
+    for (fname, entry) in entries.into_iter() {
+        let permit = semaphore.clone().acquire_owned().await.unwrap();
+        let out_path = out_path.clone();
+        let state_tree = state_tree.clone();
+        let progress = progress.clone();
+        let h = tokio::spawn(async move {
+            let url = entry.url.clone();
+            let i = &url;
+            let outcome = match fetch(&CLIENT, i, None, "covers").await {
+                Ok(r) if r.status().is_success() => {
+                    let fpath = PathBuf::from(format!("{out_path}/{fname}"));
+                    match save_body(r, fpath.to_str().unwrap(), max_size).await {
+                        Ok(bytes) => match verify(entry, fpath.clone()).await {
+                            Ok(()) => {
+                                State::Done.set(&state_tree, &fname);
+                                Outcome::Stored(bytes)
+                            }
+                            Err(e) => {
+                                error!("{i} mismatch: {e}");
+                                fs::remove_file(&fpath).ok();
+                                State::retry(&state_tree, &fname);
+                                Outcome::Failed
+                            }
+                        },
+                        Err(SaveError::TooLarge(size)) => {
+                            error!("{i} exceeds {size} bytes");
+                            State::TooLarge(size).set(&state_tree, &fname);
+                            Outcome::Failed
+                        }
+                        Err(e) => {
+                            error!("{i}: {e}");
+                            State::retry(&state_tree, &fname);
+                            Outcome::Failed
+                        }
                     }
                 }
-
-                if let Ok(r) = response {
-                    if r.status().is_success() {
-                        let mut dest = {
-                            let fname = r
-                                .url()
-                                .path_segments()
-                                .and_then(|segments| segments.last())
-                                .and_then(|name| if name.is_empty() { None } else { Some(name) })
-                                .unwrap_or("tmp.bin");
-
-                            println!("file to download: '{}'", fname);
-
-                            let fpath = format!("{out_path}/{fname}");
-
-                            File::create(fpath).unwrap()
-                        };
-                        let content = r.bytes().await.unwrap();
-                        copy(&mut content.as_ref(), &mut dest).unwrap();
-                        println!("finished {}", &i);
+                Ok(r) => {
+                    error!("{i}: {}", r.status());
+                    State::failed(&state_tree, &fname, r.status().as_u16());
+                    if r.status().as_u16() == 404 {
+                        Outcome::NotFound
                     } else {
-                        println!("{:?}", r);
+                        Outcome::Failed
                     }
-                } else {
-                    println!("{:?}", response);
                 }
-                drop(permit);
-            });
+                Err(e) => {
+                    error!("{i}: {e}");
+                    State::retry(&state_tree, &fname);
+                    Outcome::Failed
+                }
+            };
+            METRICS.outcome("covers", &outcome);
+            progress.record(&outcome);
+            drop(permit);
+        });
 
-            handers.push(h);
-        }
+        handers.push(h);
     }
 
     for i in handers {
         i.await.unwrap();
     }
+    progress.finish();
+
+    db.flush_async().await.unwrap();
 }
@@ -1,11 +1,28 @@
 use datura::{
-    config::CONFIG,
-    download::Web,
+    api,
+    config::{command_args, CONFIG},
+    download::{u32_to_ivec, Site, Trees, Web},
     extract::{Album, Book, Movie},
+    index::{lookup, reindex},
+    metrics::{self, METRICS},
+    people::appearances,
+    progress::{Counts, Progress},
+    ratings::history,
+    runs::{now, KindRun, Run},
+    schema::migrate,
+    search::search,
+    verify::{verify, verify_runs},
 };
-use std::{path::PathBuf, sync::Arc};
+use sled::Db;
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+#[cfg(unix)]
+use tokio::signal::unix::{signal, Signal, SignalKind};
 use tokio::sync::Semaphore;
-use tracing::info;
+use tracing::{error, info};
 use tracing_subscriber::{prelude::__tracing_subscriber_SubscriberExt, util::SubscriberInitExt};
 
 #[tokio::main]
@@ -15,133 +32,376 @@ async fn main() {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
-    let db_url = &CONFIG.db;
-    let config = sled::Config::default().path(db_url).use_compression(true);
-    let db = config.open().unwrap();
-    info!(%db_url);
+    let db = CONFIG.open_db();
 
-    //movies
+    let args = command_args();
+    let args: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    let mode = match args.as_slice() {
+        [] => Mode::Full,
+        ["refresh"] => Mode::Refresh,
+        ["daemon"] => Mode::Incremental,
+        ["runs", "list"] => {
+            for (id, run) in Run::list(&db) {
+                println!("{id:>6} {}", run.summary());
+            }
+            return;
+        }
+        ["runs", "show", id] => {
+            let id = id.parse().expect("run id should be a number");
+            match Run::get(&db, id) {
+                Some(run) => print!("{run}"),
+                None => println!("run {id} not found"),
+            }
+            return;
+        }
+        ["lookup", flag, key] if flag.starts_with("--") => {
+            let found = lookup(&db, &flag[2..], key);
+            if found.is_empty() {
+                println!("not found");
+            }
+            for one in found {
+                println!("{} {} {}", one.kind, one.id, one.title);
+            }
+            return;
+        }
+        ["people", name, rest @ ..] => {
+            let url = match rest {
+                [] => None,
+                ["--url", url] => Some(*url),
+                _ => panic!("unknown option: {}", rest.join(" ")),
+            };
+            let found = appearances(&db, name, url);
+            if found.is_empty() {
+                println!("not found");
+            }
+            for one in found {
+                println!(
+                    "{} {} {} {} {} {}",
+                    one.name,
+                    one.url.as_deref().unwrap_or("-"),
+                    one.kind,
+                    one.id,
+                    one.roles.join(","),
+                    one.title.as_deref().unwrap_or("-")
+                );
+            }
+            return;
+        }
+        ["search", query, rest @ ..] => {
+            let mut kind = None;
+            let mut limit = 20;
+            for opt in rest.chunks(2) {
+                match opt {
+                    ["--kind", k] => kind = Some(*k),
+                    ["--limit", n] => limit = n.parse().expect("limit should be a number"),
+                    _ => panic!("unknown option: {}", opt.join(" ")),
+                }
+            }
+            for hit in search(&db, query, kind, limit) {
+                println!("{:.3} {} {} {}", hit.score, hit.kind, hit.id, hit.title);
+            }
+            return;
+        }
+        ["migrate"] => {
+            for m in migrate(&db) {
+                info!(
+                    "{}: {} items, {} migrated, {} undecodable",
+                    m.kind, m.total, m.migrated, m.failed
+                );
+            }
+            db.flush_async().await.unwrap();
+            return;
+        }
+        ["verify", rest @ ..] => {
+            let repair = match rest {
+                [] => false,
+                ["--repair"] => true,
+                _ => panic!("unknown option: {}", rest.join(" ")),
+            };
+            let issues: Vec<_> = [
+                verify::<Movie>(&db, &CONFIG.movie_cover_path, repair),
+                verify::<Book>(&db, &CONFIG.book_cover_path, repair),
+                verify::<Album>(&db, &CONFIG.album_cover_path, repair),
+                verify_runs(&db, repair),
+            ]
+            .into_iter()
+            .flatten()
+            .collect();
+            for issue in &issues {
+                println!("{issue}");
+            }
+            println!(
+                "{} issues{}",
+                issues.len(),
+                if repair { ", repaired" } else { "" }
+            );
+            db.flush_async().await.unwrap();
+            return;
+        }
+        ["capture", kind, id, name] => {
+            let id = id.parse().expect("id should be a number");
+            // written under tests/fixtures, and nowhere else
+            if name.is_empty()
+                || !name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+            {
+                panic!("fixture name should be letters, digits, `_` and `-`: {name}");
+            }
+            let trees = match *kind {
+                "movie" => Trees::open::<Movie>(&db),
+                "book" => Trees::open::<Book>(&db),
+                "album" => Trees::open::<Album>(&db),
+                _ => panic!("unknown kind: {kind}"),
+            };
+            match trees.pages.get(u32_to_ivec(id)).unwrap() {
+                Some(page) => {
+                    let fpath = format!("tests/fixtures/{kind}/{name}.html");
+                    std::fs::write(&fpath, page).unwrap();
+                    println!("{fpath}");
+                }
+                None => println!("no page of {kind} {id}, crawl with keep_pages = true"),
+            }
+            return;
+        }
+        ["ratings", kind, id] => {
+            let id = id.parse().expect("id should be a number");
+            let trees = match *kind {
+                "movie" => Trees::open::<Movie>(&db),
+                "book" => Trees::open::<Book>(&db),
+                "album" => Trees::open::<Album>(&db),
+                _ => panic!("unknown kind: {kind}"),
+            };
+            let history = history(&trees.ratings, id);
+            if history.is_empty() {
+                println!("no rating of {kind} {id}");
+            }
+            let show = |n: Option<u32>| n.map_or("-".to_owned(), |n| n.to_string());
+            for (at, rating) in history {
+                println!(
+                    "{at} {} {} {} {}",
+                    rating.score.map_or("-".to_owned(), |s| format!("{s:.1}")),
+                    show(rating.count),
+                    show(rating.marks),
+                    show(rating.reviews)
+                );
+            }
+            return;
+        }
+        ["reindex"] => {
+            for (kind, cnt) in reindex(&db) {
+                info!("{kind}: {cnt} indexed");
+            }
+            db.flush_async().await.unwrap();
+            return;
+        }
+        _ => panic!("unknown command: {}", args.join(" ")),
+    };
 
-    let site = "https://neodb.social/movies";
-    let movies_tree = db.open_tree("movies").unwrap();
-    let movie_404_tree = db.open_tree("movie_404").unwrap();
-    let ids = Movie::check_ids(&movies_tree, &movie_404_tree, site).await;
+    let mut term = term();
 
-    let mut handers = vec![];
-    let semaphore = Arc::new(Semaphore::new(100));
-    for id in ids {
-        let permit = semaphore.clone().acquire_owned().await.unwrap();
-        let movies_tree = movies_tree.clone();
-        let movie_404_tree = movie_404_tree.clone();
-
-        let h = tokio::spawn(async move {
-            Movie::get_data(site, id, &movies_tree, &movie_404_tree).await;
-            drop(permit);
-        });
-
-        handers.push(h);
+    if let Some(ref addr) = CONFIG.metrics_addr {
+        if let Err(e) = metrics::serve(addr) {
+            error!("{e}, crawling without metrics");
+        }
     }
-
-    for h in handers {
-        h.await.unwrap();
+    if let Some(ref addr) = CONFIG.serve_addr {
+        if let Err(e) = api::spawn(&db, &CONFIG, addr) {
+            error!("{e}, crawling without it");
+        }
     }
 
-    // download cover
-    let dir = PathBuf::from(&CONFIG.movie_cover_path);
-    if !dir.exists() {
-        std::fs::create_dir_all(&dir).unwrap();
+    if mode == Mode::Incremental {
+        // the first cycle also retries the ids missed by former runs
+        let mut mode = Mode::Full;
+        let interval = Duration::from_secs(CONFIG.interval);
+        loop {
+            if crawl_all(&db, mode, &mut term).await {
+                break;
+            }
+            mode = next_mode(&db);
+            info!("next crawl in {}s, {}", CONFIG.interval, mode.as_str());
+            tokio::select! {
+                _ = tokio::time::sleep(interval) => {}
+                _ = stop(&mut term) => break,
+            }
+        }
+    } else {
+        crawl_all(&db, mode, &mut term).await;
     }
 
-    let movie_covers_tree = db.open_tree("movie_covers").unwrap();
-    let ids = Movie::check_ids(&movie_covers_tree, &movie_404_tree, site).await;
-
-    let mut handers = vec![];
-    for id in ids {
-        let movies_tree = movies_tree.clone();
-        let movie_covers_tree = movie_covers_tree.clone();
-        let permit = semaphore.clone().acquire_owned().await.unwrap();
-        let h = tokio::spawn(async move {
-            Movie::dl_cover(
-                id,
-                &movies_tree,
-                &movie_covers_tree,
-                &CONFIG.movie_cover_path,
-            )
-            .await;
-            drop(permit);
-        });
-        handers.push(h);
-    }
+    db.flush_async().await.unwrap();
+    info!("stopped");
+}
 
-    for h in handers {
-        h.await.unwrap();
-    }
+/// the SIGTERM listener, `None` if it cannot be registered
+#[cfg(unix)]
+type Term = Option<Signal>;
 
-    // books
-    let site = "https://neodb.social/books";
-    let books_tree = db.open_tree("books").unwrap();
-    let book_404_tree = db.open_tree("book_404").unwrap();
-    let ids = Book::check_ids(&books_tree, &book_404_tree, site).await;
+/// no SIGTERM off unix, only ctrl-c
+#[cfg(not(unix))]
+struct Term;
 
-    let mut handers = vec![];
-    let semaphore = Arc::new(Semaphore::new(100));
+#[cfg(unix)]
+fn term() -> Term {
+    signal(SignalKind::terminate())
+        .map_err(|e| error!("SIGTERM: {e}, stopping on ctrl-c only"))
+        .ok()
+}
 
-    for id in ids {
-        let book_404_tree = book_404_tree.clone();
-        let permit = semaphore.clone().acquire_owned().await.unwrap();
-        let books_tree = books_tree.clone();
-        let h = tokio::spawn(async move {
-            Book::get_data(site, id, &books_tree, &book_404_tree).await;
-            drop(permit);
-        });
+#[cfg(not(unix))]
+fn term() -> Term {
+    Term
+}
 
-        handers.push(h);
+/// ctrl-c or SIGTERM
+#[cfg(unix)]
+async fn stop(term: &mut Term) {
+    match term {
+        Some(term) => tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = term.recv() => {}
+        },
+        None => {
+            let _ = tokio::signal::ctrl_c().await;
+        }
     }
+}
 
-    for h in handers {
-        h.await.unwrap();
-    }
+/// ctrl-c
+#[cfg(not(unix))]
+async fn stop(_: &mut Term) {
+    let _ = tokio::signal::ctrl_c().await;
+}
 
-    // download cover
-    let dir = PathBuf::from(&CONFIG.book_cover_path);
-    if !dir.exists() {
-        std::fs::create_dir_all(&dir).unwrap();
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// all missing ids up to the newest one
+    Full,
+    /// only ids after the last stored one
+    Incremental,
+    /// fetch all stored items and covers again, conditionally on their `ETag` / `Last-Modified`
+    Refresh,
+}
+
+impl Mode {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Mode::Full => "full",
+            Mode::Incremental => "incremental",
+            Mode::Refresh => "refresh",
+        }
     }
+}
 
-    let book_covers_tree = db.open_tree("book_covers").unwrap();
-    let ids = Book::check_ids(&book_covers_tree, &book_404_tree, site).await;
+/// [Mode::Refresh] once `refresh_interval` has passed since the last refresh, or since the
+/// first run if there was none, so the ratings of the stored items keep being recorded;
+/// [Mode::Incremental] otherwise.
+fn next_mode(db: &Db) -> Mode {
+    if CONFIG.refresh_interval == 0 {
+        return Mode::Incremental;
+    }
+    let runs = Run::list(db);
+    let since = runs
+        .iter()
+        .rev()
+        .find(|(_, run)| run.mode == Mode::Refresh.as_str())
+        .or(runs.first())
+        .map_or_else(now, |(_, run)| run.start);
+    if now().saturating_sub(since) >= CONFIG.refresh_interval {
+        Mode::Refresh
+    } else {
+        Mode::Incremental
+    }
+}
 
-    let mut handers = vec![];
-    for id in ids {
-        let books_tree = books_tree.clone();
-        let book_covers_tree = book_covers_tree.clone();
-        let permit = semaphore.clone().acquire_owned().await.unwrap();
-        let h = tokio::spawn(async move {
-            Book::dl_cover(id, &books_tree, &book_covers_tree, &CONFIG.book_cover_path).await;
-            drop(permit);
-        });
-        handers.push(h);
+/// Crawl every kind and record the run, also when it is stopped by a signal. Returns whether
+/// it was stopped.
+async fn crawl_all(db: &Db, mode: Mode, term: &mut Term) -> bool {
+    let start = now();
+    let progresses = Mutex::new(vec![]);
+    let stopped = tokio::select! {
+        _ = async {
+            crawl::<Movie>(db, &CONFIG.movie_cover_path, mode, &progresses).await;
+            crawl::<Book>(db, &CONFIG.book_cover_path, mode, &progresses).await;
+            crawl::<Album>(db, &CONFIG.album_cover_path, mode, &progresses).await;
+        } => false,
+        _ = stop(term) => true,
+    };
+    if stopped {
+        info!("stopping, recording the run so far");
     }
+    let run = Run {
+        mode: mode.as_str().to_owned(),
+        start,
+        end: now(),
+        kinds: kind_runs(&progresses.lock().unwrap()),
+    };
+    let id = run.save(db);
+    info!("run {id}: {}", run.summary());
+    db.flush_async().await.unwrap();
+    stopped
+}
 
-    for h in handers {
-        h.await.unwrap();
+/// counters of the [Progress] of the items and covers of each kind, named `{kind}` and
+/// `{kind}_covers`
+fn kind_runs(progresses: &[Arc<Progress>]) -> Vec<KindRun> {
+    let mut kinds: Vec<KindRun> = vec![];
+    for progress in progresses {
+        match progress.name().strip_suffix("_covers") {
+            Some(kind) => {
+                if let Some(one) = kinds.iter_mut().find(|one| one.kind == kind) {
+                    one.covers = progress.counts();
+                }
+            }
+            None => kinds.push(KindRun {
+                kind: progress.name().to_owned(),
+                items: progress.counts(),
+                covers: Counts::default(),
+            }),
+        }
     }
+    kinds
+}
+
+/// Get new items of `T` and download their covers. Each [Progress] is added to `progresses`
+/// as soon as it starts.
+async fn crawl<T: Web + 'static>(
+    db: &Db,
+    cover_path: &'static str,
+    mode: Mode,
+    progresses: &Mutex<Vec<Arc<Progress>>>,
+) {
+    let site = Arc::new(Site::new(&CONFIG.site));
+    let mut trees = Trees::open::<T>(db);
+    trees.keep_pages = CONFIG.keep_pages;
+    let first_new = if trees.data.is_empty() {
+        1
+    } else {
+        T::last_id(&trees.data) + 1
+    };
+    let ids = match mode {
+        Mode::Full => T::check_ids(&trees.data, &trees.not_found, &site).await,
+        Mode::Incremental => T::check_new_ids(&trees.data, &trees.not_found, &site).await,
+        Mode::Refresh => T::stored_ids(&trees.data),
+    };
 
-    // album
-    let site = "https://neodb.social/music/album";
-    let albums_tree = db.open_tree("albums").unwrap();
-    let album_404_tree = db.open_tree("album_404").unwrap();
-    let ids = Album::check_ids(&albums_tree, &album_404_tree, site).await;
+    let interval = Duration::from_secs(CONFIG.progress_interval);
+    let progress = Progress::new(T::KIND, ids.len(), CONFIG.progress_bar);
+    progress.spawn_reporter(interval);
+    progresses.lock().unwrap().push(progress.clone());
 
     let mut handers = vec![];
     let semaphore = Arc::new(Semaphore::new(100));
-
+    METRICS.watch_semaphore(T::KIND, &semaphore, 100);
     for id in ids {
-        let album_404_tree = album_404_tree.clone();
         let permit = semaphore.clone().acquire_owned().await.unwrap();
-        let albums_tree = albums_tree.clone();
+        let trees = trees.clone();
+        let site = site.clone();
+        let progress = progress.clone();
         let h = tokio::spawn(async move {
-            Album::get_data(site, id, &albums_tree, &album_404_tree).await;
+            let outcome = T::get_data(&site, id, &trees).await;
+            progress.record(&outcome);
             drop(permit);
         });
 
@@ -151,29 +411,40 @@ async fn main() {
     for h in handers {
         h.await.unwrap();
     }
+    progress.finish();
 
     // download cover
-    let dir = PathBuf::from(&CONFIG.album_cover_path);
+    let dir = PathBuf::from(cover_path);
     if !dir.exists() {
         std::fs::create_dir_all(&dir).unwrap();
     }
 
-    let album_covers_tree = db.open_tree("album_covers").unwrap();
-    let ids = Album::check_ids(&album_covers_tree, &album_404_tree, site).await;
+    let ids = match mode {
+        Mode::Full => T::check_ids(&trees.covers, &trees.not_found, &site).await,
+        Mode::Incremental => T::stored_ids_from(&trees.data, first_new)
+            .into_iter()
+            .filter(|id| !trees.covers.contains_key(u32_to_ivec(*id)).unwrap())
+            .collect(),
+        Mode::Refresh => T::stored_ids(&trees.covers),
+    };
+
+    let progress = Progress::new(
+        &format!("{}_covers", T::KIND),
+        ids.len(),
+        CONFIG.progress_bar,
+    );
+    progress.spawn_reporter(interval);
+    progresses.lock().unwrap().push(progress.clone());
 
     let mut handers = vec![];
     for id in ids {
-        let albums_tree = albums_tree.clone();
-        let album_covers_tree = album_covers_tree.clone();
+        let trees = trees.clone();
+        let progress = progress.clone();
         let permit = semaphore.clone().acquire_owned().await.unwrap();
+        let site = site.clone();
         let h = tokio::spawn(async move {
-            Album::dl_cover(
-                id,
-                &albums_tree,
-                &album_covers_tree,
-                &CONFIG.album_cover_path,
-            )
-            .await;
+            let outcome = T::dl_cover(&site, id, &trees, cover_path, CONFIG.max_file_size).await;
+            progress.record(&outcome);
             drop(permit);
         });
         handers.push(h);
@@ -182,4 +453,5 @@ async fn main() {
     for h in handers {
         h.await.unwrap();
     }
+    progress.finish();
 }
@@ -0,0 +1,24 @@
+use datura::{
+    api::{self, DEFAULT_ADDR},
+    config::CONFIG,
+};
+use tracing::error;
+use tracing_subscriber::{prelude::__tracing_subscriber_SubscriberExt, util::SubscriberInitExt};
+
+#[tokio::main]
+async fn main() {
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::EnvFilter::new("info"))
+        .with(tracing_subscriber::fmt::layer())
+        .init();
+
+    let db = CONFIG.open_db();
+    let addr = CONFIG.serve_addr.as_deref().unwrap_or(DEFAULT_ADDR);
+    match api::spawn(&db, &CONFIG, addr) {
+        Ok((_, handle)) => handle.await.unwrap(),
+        Err(e) => {
+            error!("{e}");
+            std::process::exit(1);
+        }
+    }
+}
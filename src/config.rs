@@ -1,15 +1,66 @@
+use crate::download::{DEFAULT_MAX_FILE_SIZE, SITE};
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use std::fs::read_to_string;
+use tracing::{error, info};
 
 pub static CONFIG: Lazy<Config> = Lazy::new(Config::load_config);
 
 #[derive(Serialize, Deserialize)]
 pub struct Config {
     pub db: String,
+    /// the neodb instance to crawl
+    #[serde(default = "default_site")]
+    pub site: String,
     pub book_cover_path: String,
     pub movie_cover_path: String,
     pub album_cover_path: String,
+    /// covers larger than this many bytes are not saved
+    #[serde(default = "default_max_file_size")]
+    pub max_file_size: u64,
+    /// seconds between progress reports
+    #[serde(default = "default_progress_interval")]
+    pub progress_interval: u64,
+    /// draw a progress bar on stderr
+    #[serde(default)]
+    pub progress_bar: bool,
+    /// serve prometheus metrics on this address, eg. `127.0.0.1:9100`
+    #[serde(default)]
+    pub metrics_addr: Option<String>,
+    /// keep the raw html of every fetched page in `{kind}_pages`, not only of those
+    /// failing to parse
+    #[serde(default)]
+    pub keep_pages: bool,
+    /// seconds between two crawls in daemon mode
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+    /// seconds between two refreshes of all stored items in daemon mode, `0` for never
+    #[serde(default = "default_refresh_interval")]
+    pub refresh_interval: u64,
+    /// address of the api, served by `neodb` too if set; `serve` defaults to
+    /// [crate::api::DEFAULT_ADDR]
+    #[serde(default)]
+    pub serve_addr: Option<String>,
+}
+
+fn default_site() -> String {
+    SITE.to_owned()
+}
+
+fn default_max_file_size() -> u64 {
+    DEFAULT_MAX_FILE_SIZE
+}
+
+fn default_progress_interval() -> u64 {
+    10
+}
+
+fn default_interval() -> u64 {
+    3600
+}
+
+fn default_refresh_interval() -> u64 {
+    7 * 24 * 3600
 }
 
 impl Config {
@@ -21,4 +72,28 @@ impl Config {
         let config: Config = toml::from_str(&config_toml_content).unwrap();
         config
     }
+
+    /// Open [Config::db], or exit if it cannot be opened. sled locks the db for as long as it
+    /// is open, so this is where a second process on the same db stops.
+    pub fn open_db(&self) -> sled::Db {
+        let config = sled::Config::default().path(&self.db).use_compression(true);
+        match config.open() {
+            Ok(db) => {
+                info!(db_url = %self.db);
+                db
+            }
+            Err(e) => {
+                error!(
+                    "{}: {e}, is another neodb or serve already running on it?",
+                    self.db
+                );
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// command line arguments after the config file
+pub fn command_args() -> Vec<String> {
+    std::env::args().skip(2).collect()
 }
@@ -1,12 +1,38 @@
-use crate::extract::{Album, Book, Movie};
+use crate::{
+    extract::{Album, Book, Movie, ParseError, Person, Rating},
+    isbn::normalize_barcode,
+    metrics::METRICS,
+    people::{clear_kind, index_people, unindex_people},
+    ratings,
+    runs::now,
+    schema::{decode, encode, Versioned},
+    search::index_doc,
+    sources::ExternalSource,
+};
 use async_trait::async_trait;
 use bincode::{config::standard, Decode, Encode};
 use once_cell::sync::Lazy;
-use reqwest::{Client, StatusCode};
-use sled::{IVec, Tree};
-use std::time::Duration;
+use reqwest::{
+    header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+    Client, Response, StatusCode,
+};
+use sled::{
+    transaction::{TransactionResult, Transactional},
+    Db, IVec, Tree,
+};
+use std::{
+    fmt,
+    path::Path,
+    time::{Duration, Instant},
+};
+use tokio::{fs::File, io::AsyncWriteExt};
 use tracing::{error, info, instrument};
 
+pub const SITE: &str = "https://neodb.social";
+
+/// 20 MiB, far above any cover image
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 20 * 1024 * 1024;
+
 pub static CLIENT: Lazy<Client> = Lazy::new(|| {
     Client::builder()
         .timeout(Duration::from_secs(30))
@@ -14,6 +40,29 @@ pub static CLIENT: Lazy<Client> = Lazy::new(|| {
         .unwrap()
 });
 
+/// a neodb instance and the client to reach it
+#[derive(Clone)]
+pub struct Site {
+    /// eg. `https://neodb.social`, without trailing slash
+    pub base: String,
+    pub client: Client,
+}
+
+impl Site {
+    pub fn new(base: &str) -> Self {
+        Site {
+            base: base.trim_end_matches('/').to_owned(),
+            client: CLIENT.clone(),
+        }
+    }
+}
+
+impl Default for Site {
+    fn default() -> Self {
+        Site::new(SITE)
+    }
+}
+
 pub trait Cover {
     fn cover(self) -> Option<String>;
 }
@@ -36,14 +85,297 @@ impl Cover for Album {
     }
 }
 
+/// result of fetching one item or cover
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// stored, with the bytes downloaded
+    Stored(u64),
+    /// answered with 304
+    Unchanged,
+    NotFound,
+    Failed,
+    /// fetched, but the page could not be extracted
+    ParseError,
+    /// nothing to fetch, eg. an item without cover
+    Skipped,
+}
+
+/// sled trees of one kind of item
+#[derive(Clone)]
+pub struct Trees {
+    /// id -> item, see [crate::schema]
+    pub data: Tree,
+    /// ids which are 404
+    pub not_found: Tree,
+    /// ids whose cover is downloaded
+    pub covers: Tree,
+    /// id -> [Validators] of the item page
+    pub etags: Tree,
+    /// id -> [Validators] of the cover
+    pub cover_etags: Tree,
+    /// id -> the max file size its cover exceeded, skipped until the limit is raised
+    pub cover_too_large: Tree,
+    /// [index_key] of key and id -> empty, one tree for each of [Web::INDEXES]
+    pub indexes: Vec<Tree>,
+    /// full-text postings, see [crate::search]
+    pub fts: Tree,
+    /// id -> tokens of the item in `fts`
+    pub fts_docs: Tree,
+    /// id -> raw html of the pages failing to parse, or of all with `keep_pages`, for
+    /// fixtures and debugging
+    pub pages: Tree,
+    /// person -> items, shared by all kinds, see [crate::people]
+    pub people: Tree,
+    /// id time -> [Rating], see [crate::ratings]
+    pub ratings: Tree,
+    /// keep the pages which parse fine too
+    pub keep_pages: bool,
+}
+
+impl Trees {
+    /// open the trees of `T`, eg. `movies`, `movie_404`, `movie_covers` for [Movie]
+    pub fn open<T: Web>(db: &Db) -> Self {
+        let kind = T::KIND;
+        Trees {
+            data: db.open_tree(format!("{kind}s")).unwrap(),
+            not_found: db.open_tree(format!("{kind}_404")).unwrap(),
+            covers: db.open_tree(format!("{kind}_covers")).unwrap(),
+            etags: db.open_tree(format!("{kind}_etags")).unwrap(),
+            cover_etags: db.open_tree(format!("{kind}_cover_etags")).unwrap(),
+            cover_too_large: db.open_tree(format!("{kind}_cover_too_large")).unwrap(),
+            indexes: T::INDEXES
+                .iter()
+                .map(|name| db.open_tree(format!("{kind}_{name}")).unwrap())
+                .collect(),
+            fts: db.open_tree(format!("{kind}_fts")).unwrap(),
+            fts_docs: db.open_tree(format!("{kind}_fts_docs")).unwrap(),
+            pages: db.open_tree(format!("{kind}_pages")).unwrap(),
+            people: db.open_tree("people").unwrap(),
+            ratings: db.open_tree(format!("{kind}_ratings")).unwrap(),
+            keep_pages: false,
+        }
+    }
+
+    /// Store the item `id` and point its index keys to it, in one transaction. Keys which
+    /// the former version of the item had and are gone now are dropped. The full-text
+    /// index and the people are updated afterwards, and the rating recorded if it changed.
+    ///
+    /// Those are outside the transaction: if the process dies in between, [Trees::reindex]
+    /// rebuilds the full-text index and the people, and the rating is recorded on the next
+    /// fetch of the item.
+    pub fn store<T: Web>(&self, id: u32, one: &T) {
+        let former = self
+            .data
+            .get(u32_to_ivec(id))
+            .unwrap()
+            .and_then(|v| decode::<T>(&v));
+        let encoded = encode(one);
+        let new_keys = one.index_keys();
+        let mut trees = vec![self.data.clone()];
+        trees.extend(self.indexes.iter().cloned());
+
+        let res: TransactionResult<()> = trees.as_slice().transaction(|txs| {
+            let old = txs[0].insert(u32_to_ivec(id), encoded.as_slice())?;
+            let old_keys = old
+                .and_then(|v| decode::<T>(&v))
+                .map(|old| old.index_keys())
+                .unwrap_or_default();
+
+            for (i, tx) in txs[1..].iter().enumerate() {
+                let new = new_keys
+                    .get(i)
+                    .map(|keys| keys.as_slice())
+                    .unwrap_or_default();
+                for old_key in old_keys.get(i).into_iter().flatten() {
+                    if !new.contains(old_key) {
+                        tx.remove(index_key(old_key, id))?;
+                    }
+                }
+                for key in new {
+                    tx.insert(index_key(key, id), &[])?;
+                }
+            }
+            Ok(())
+        });
+        res.unwrap();
+
+        index_doc(&self.fts, &self.fts_docs, id, &one.search_fields());
+        if let Some(former) = former {
+            unindex_people(&self.people, T::KIND, id, &former.people());
+        }
+        index_people(&self.people, T::KIND, id, &one.people());
+        ratings::record(&self.ratings, id, one.rating(), now());
+    }
+
+    /// Rebuild the indexes and the full-text index from the stored items, returning how
+    /// many items are indexed.
+    pub fn reindex<T: Web>(&self) -> usize {
+        for tree in &self.indexes {
+            tree.clear().unwrap();
+        }
+        self.fts.clear().unwrap();
+        self.fts_docs.clear().unwrap();
+        clear_kind(&self.people, T::KIND);
+        let mut cnt = 0;
+        for kv in self.data.iter() {
+            let (k, v) = kv.unwrap();
+            if let Some(one) = decode::<T>(&v) {
+                for (tree, keys) in self.indexes.iter().zip(one.index_keys()) {
+                    for key in keys {
+                        tree.insert(index_key(&key, ivec_to_u32(&k)), &[]).unwrap();
+                    }
+                }
+                index_doc(
+                    &self.fts,
+                    &self.fts_docs,
+                    ivec_to_u32(&k),
+                    &one.search_fields(),
+                );
+                index_people(&self.people, T::KIND, ivec_to_u32(&k), &one.people());
+                cnt += 1;
+            }
+        }
+        cnt
+    }
+
+    /// Find the items whose key in the index `name` is `key`, by id.
+    pub fn lookup<T: Web>(&self, name: &str, key: &str) -> Vec<(u32, T)> {
+        let Some(i) = T::INDEXES.iter().position(|n| *n == name) else {
+            return vec![];
+        };
+        let mut prefix = key.as_bytes().to_vec();
+        prefix.push(0);
+        self.indexes[i]
+            .scan_prefix(prefix)
+            .keys()
+            .filter_map(|k| {
+                let (_, id) = split_index_key(&k.unwrap())?;
+                let v = self.data.get(u32_to_ivec(id)).unwrap()?;
+                Some((id, decode(&v)?))
+            })
+            .collect()
+    }
+}
+
+/// `ETag` and `Last-Modified` of a response, sent back as `If-None-Match` and
+/// `If-Modified-Since` so an unchanged page or cover is answered with 304
+#[derive(Debug, Default, Encode, Decode)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    fn from_response(r: &Response) -> Self {
+        let header = |name| {
+            r.headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(|s| s.to_owned())
+        };
+        Validators {
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+        }
+    }
+
+    /// `None` if there are none, or they cannot be decoded
+    fn get(tree: &Tree, id: u32) -> Option<Self> {
+        let v = tree.get(u32_to_ivec(id)).unwrap()?;
+        bincode::decode_from_slice(&v, standard())
+            .ok()
+            .map(|(validators, _)| validators)
+    }
+
+    fn save(&self, tree: &Tree, id: u32) {
+        if self.etag.is_none() && self.last_modified.is_none() {
+            tree.remove(u32_to_ivec(id)).unwrap();
+        } else {
+            let encoded = bincode::encode_to_vec(self, standard()).unwrap();
+            tree.insert(u32_to_ivec(id), encoded).unwrap();
+        }
+    }
+}
+
+/// GET `url` with `client`, retrying twice on network errors. Cache validators of the last fetch are
+/// sent as conditional headers, if any. Every attempt is recorded in [METRICS] under `kind`.
+pub async fn fetch(
+    client: &Client,
+    url: &str,
+    validators: Option<&Validators>,
+    kind: &str,
+) -> Result<Response, reqwest::Error> {
+    let request = || async {
+        let mut req = client.get(url);
+        if let Some(v) = validators {
+            if let Some(ref etag) = v.etag {
+                req = req.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(ref last_modified) = v.last_modified {
+                req = req.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+        let start = Instant::now();
+        let response = req.send().await;
+        let status = response.as_ref().ok().map(|r| r.status().as_u16());
+        METRICS.request(kind, status, start.elapsed());
+        response
+    };
+
+    let mut response = request().await;
+    let mut cnt = 0;
+    while let Err(ref e) = response {
+        error!("{}", e);
+        response = request().await;
+        cnt += 1;
+        if cnt >= 2 {
+            break;
+        }
+    }
+    response
+}
+
 #[async_trait]
-pub trait Web: Encode + Decode + Cover + for<'a> From<&'a str> {
-    #[instrument(skip(db, filter_db))]
-    async fn check_ids(db: &Tree, filter_db: &Tree, site: &str) -> Vec<u32> {
+pub trait Web: Versioned + Cover + Send + for<'a> TryFrom<&'a str, Error = ParseError> {
+    /// tree name prefix, eg. `movie`
+    const KIND: &'static str;
+    /// url path on the site, eg. `movies`
+    const PATH: &'static str;
+
+    /// names of the secondary indexes, the trees are `{KIND}_{name}`
+    const INDEXES: &'static [&'static str] = &[];
+
+    /// keys of this item in each of [Web::INDEXES], same order; several in one index, eg.
+    /// the url of each source
+    fn index_keys(&self) -> Vec<Vec<String>> {
+        vec![]
+    }
+
+    fn title(&self) -> &str;
+
+    fn rating(&self) -> &Rating;
+
+    /// texts to search in, with their weights
+    fn search_fields(&self) -> Vec<(&str, u32)>;
+
+    /// people credited on the item, with their role, eg. `director`
+    fn people(&self) -> Vec<(&Person, &'static str)> {
+        vec![]
+    }
+
+    /// url of the item page
+    fn url(site: &Site, id: u32) -> String {
+        format!("{}/{}/{id}", site.base, Self::PATH)
+    }
+
+    #[instrument(skip(db, filter_db, site))]
+    async fn check_ids(db: &Tree, filter_db: &Tree, site: &Site) -> Vec<u32> {
         let last_id = Self::last_id(db);
         info!(%last_id);
 
-        let newest_id = Self::find_newest_id(last_id, site).await;
+        let Some(newest_id) = Self::find_newest_id(last_id, site).await else {
+            return vec![];
+        };
         info!(%newest_id);
 
         let ids: Vec<u32> = (1..=newest_id)
@@ -57,6 +389,26 @@ pub trait Web: Encode + Decode + Cover + for<'a> From<&'a str> {
         ids
     }
 
+    /// Like [Web::check_ids], but only ids after the last stored one.
+    #[instrument(skip(db, filter_db, site))]
+    async fn check_new_ids(db: &Tree, filter_db: &Tree, site: &Site) -> Vec<u32> {
+        let last_id = Self::last_id(db);
+        info!(%last_id);
+
+        let Some(newest_id) = Self::find_newest_id(last_id, site).await else {
+            return vec![];
+        };
+        info!(%newest_id);
+
+        let first = if db.is_empty() { 1 } else { last_id + 1 };
+        let ids: Vec<u32> = (first..=newest_id)
+            .filter(|id| !filter_db.contains_key(u32_to_ivec(*id)).unwrap())
+            .collect();
+
+        info!("to be gotten = {}", ids.len());
+        ids
+    }
+
     fn last_id(db: &Tree) -> u32 {
         if let Some((k, _)) = db.last().unwrap() {
             ivec_to_u32(&k)
@@ -65,132 +417,441 @@ pub trait Web: Encode + Decode + Cover + for<'a> From<&'a str> {
         }
     }
 
-    async fn find_newest_id(mut low: u32, site: &str) -> u32 {
+    /// all ids stored in `db`
+    fn stored_ids(db: &Tree) -> Vec<u32> {
+        Self::stored_ids_from(db, 0)
+    }
+
+    /// ids stored in `db` from `from` on
+    fn stored_ids_from(db: &Tree, from: u32) -> Vec<u32> {
+        db.range(u32_to_ivec(from)..)
+            .keys()
+            .map(|k| ivec_to_u32(&k.unwrap()))
+            .collect()
+    }
+
+    /// The newest id, found by probing pages after `low`. `None` if a probe fails, the ids
+    /// are looked for again on the next crawl.
+    async fn find_newest_id(mut low: u32, site: &Site) -> Option<u32> {
         let mut step = 255;
         let mut high = low + step;
-        while Self::is_ok(site, high).await.unwrap() {
+        while Self::probe(site, high).await? {
             step *= 2;
             high += step;
         }
 
         while high - low > 2 {
             let mid = low + (high - low) / 2;
-            if Self::is_ok(site, mid).await.unwrap() {
+            if Self::probe(site, mid).await? {
                 low = mid;
             } else {
                 high = mid;
             };
         }
 
-        if Self::is_ok(site, low + 1).await.unwrap() {
-            low + 1
+        if Self::probe(site, low + 1).await? {
+            Some(low + 1)
         } else {
-            low
+            Some(low)
         }
     }
 
+    /// [Web::is_ok], with the error logged
+    async fn probe(site: &Site, id: u32) -> Option<bool> {
+        Self::is_ok(site, id)
+            .await
+            .map_err(|e| error!("looking for new ids at {id}: {e}, skipped until the next crawl"))
+            .ok()
+    }
+
     #[instrument(skip(site))]
-    async fn is_ok(site: &str, id: u32) -> Result<bool, reqwest::Error> {
-        let url = format!("{site}/{id}");
-        let res = CLIENT.get(&url).send().await?.status().is_success();
+    async fn is_ok(site: &Site, id: u32) -> Result<bool, reqwest::Error> {
+        let url = Self::url(site, id);
+        let res = site.client.get(&url).send().await?.status().is_success();
         info!(%res);
         Ok(res)
     }
 
-    #[instrument(skip(db, db_404))]
-    async fn get_data(site: &str, id: u32, db: &Tree, db_404: &Tree) {
-        let url = format!("{site}/{id}");
-        let mut response = CLIENT.get(&url).send().await;
-        let mut cnt = 0;
-        while response.is_err() {
-            error!("{}", response.unwrap_err());
-            response = CLIENT.get(&url).send().await;
-            cnt += 1;
-            if cnt >= 2 {
-                break;
-            }
-        }
+    #[instrument(skip(site, trees))]
+    async fn get_data(site: &Site, id: u32, trees: &Trees) -> Outcome {
+        let url = Self::url(site, id);
+        let validators = Validators::get(&trees.etags, id);
 
-        match response {
+        let outcome = match fetch(&site.client, &url, validators.as_ref(), Self::KIND).await {
             Ok(r) => {
-                if r.status().is_success() {
+                if r.status() == StatusCode::NOT_MODIFIED {
+                    info!("not modified");
+                    Outcome::Unchanged
+                } else if r.status().is_success() {
+                    let validators = Validators::from_response(&r);
                     match r.text().await {
-                        Ok(content) => {
-                            let one = Self::from(content.as_ref());
-                            let encoded = bincode::encode_to_vec(&one, standard()).unwrap();
-                            db.insert(u32_to_ivec(id), encoded).unwrap();
-                            if id % 100 == 0 {
-                                info!("finished.");
+                        Ok(content) => match Self::try_from(content.as_ref()) {
+                            Ok(one) => {
+                                trees.store(id, &one);
+                                validators.save(&trees.etags, id);
+                                if trees.keep_pages {
+                                    trees
+                                        .pages
+                                        .insert(u32_to_ivec(id), content.as_str())
+                                        .unwrap();
+                                } else {
+                                    // the page of a former parse error
+                                    trees.pages.remove(u32_to_ivec(id)).unwrap();
+                                }
+                                Outcome::Stored(content.len() as u64)
                             }
+                            Err(e) => {
+                                error!(%e);
+                                trees
+                                    .pages
+                                    .insert(u32_to_ivec(id), content.as_str())
+                                    .unwrap();
+                                Outcome::ParseError
+                            }
+                        },
+                        Err(e) => {
+                            error!(%e);
+                            Outcome::Failed
                         }
-                        Err(e) => error!(%e),
                     }
                 } else if r.status() == StatusCode::NOT_FOUND {
                     error!("404 not found");
-                    db_404.insert(u32_to_ivec(id), &[]).unwrap();
+                    trees.not_found.insert(u32_to_ivec(id), &[]).unwrap();
+                    Outcome::NotFound
                 } else {
                     error!(?r);
+                    Outcome::Failed
                 }
             }
-            Err(e) => error!(%e),
-        }
+            Err(e) => {
+                error!(%e);
+                Outcome::Failed
+            }
+        };
+        METRICS.outcome(Self::KIND, &outcome);
+        outcome
     }
 
     fn get_cover(id: u32, db: &Tree) -> Option<String> {
-        if let Some(v) = db.get(u32_to_ivec(id)).unwrap() {
-            let (one, _): (Self, usize) = bincode::decode_from_slice(&v, standard()).unwrap();
-            one.cover()
-        } else {
-            None
+        let v = db.get(u32_to_ivec(id)).unwrap()?;
+        match decode::<Self>(&v) {
+            Some(one) => one.cover(),
+            None => {
+                error!("undecodable, run `migrate`");
+                None
+            }
         }
     }
 
-    #[instrument(skip(db, db_cover, cover_path))]
-    async fn dl_cover(id: u32, db: &Tree, db_cover: &Tree, cover_path: &str) {
-        if let Some(cover) = Self::get_cover(id, db) {
-            let url = format!("https://neodb.social{cover}");
-            let ext = url.rsplit_once('.').unwrap().1;
-            let mut response = CLIENT.get(&url).send().await;
-            let mut cnt = 0;
-            while response.is_err() {
-                error!(?response);
-                response = CLIENT.get(&url).send().await;
-                cnt += 1;
-                if cnt >= 2 {
-                    break;
-                }
+    #[instrument(skip(site, trees, cover_path, max_size))]
+    async fn dl_cover(
+        site: &Site,
+        id: u32,
+        trees: &Trees,
+        cover_path: &str,
+        max_size: u64,
+    ) -> Outcome {
+        if let Some(v) = trees.cover_too_large.get(u32_to_ivec(id)).unwrap() {
+            if v.as_ref()
+                .try_into()
+                .is_ok_and(|v| u64::from_be_bytes(v) >= max_size)
+            {
+                return Outcome::Skipped;
             }
+        }
+        let Some(cover) = Self::get_cover(id, &trees.data) else {
+            return Outcome::Skipped;
+        };
+        let url = format!("{}{cover}", site.base);
+        let ext = url.rsplit_once('.').unwrap().1;
+        let fpath = format!("{}/{}.{}", cover_path, id, ext);
 
-            match response {
-                Ok(r) => {
-                    if r.status().is_success() {
-                        let fpath = format!("{}/{}.{}", cover_path, id, ext);
-                        match r.bytes().await {
-                            Ok(content) => {
-                                tokio::fs::write(fpath, content).await.unwrap();
-                                db_cover.insert(u32_to_ivec(id), &[]).unwrap();
-                                if id % 100 == 0 {
-                                    info!("finished {}", &id);
-                                }
-                            }
-                            Err(e) => error!(%e),
+        // only revalidate a cover which is still on disk
+        let validators = if Path::new(&fpath).exists() {
+            Validators::get(&trees.cover_etags, id)
+        } else {
+            None
+        };
+
+        let kind = format!("{}_cover", Self::KIND);
+        let outcome = match fetch(&site.client, &url, validators.as_ref(), &kind).await {
+            Ok(r) => {
+                if r.status() == StatusCode::NOT_MODIFIED {
+                    info!("not modified");
+                    Outcome::Unchanged
+                } else if r.status() == StatusCode::NOT_FOUND {
+                    error!("404 not found");
+                    Outcome::NotFound
+                } else if r.status().is_success() {
+                    let validators = Validators::from_response(&r);
+                    match save_body(r, &fpath, max_size).await {
+                        Ok(bytes) => {
+                            trees.covers.insert(u32_to_ivec(id), &[]).unwrap();
+                            trees.cover_too_large.remove(u32_to_ivec(id)).unwrap();
+                            validators.save(&trees.cover_etags, id);
+                            Outcome::Stored(bytes)
+                        }
+                        Err(SaveError::TooLarge(size)) => {
+                            error!("exceeds {size} bytes, skipped from now on");
+                            trees
+                                .cover_too_large
+                                .insert(u32_to_ivec(id), &size.to_be_bytes())
+                                .unwrap();
+                            Outcome::Skipped
+                        }
+                        Err(e) => {
+                            error!(%e);
+                            Outcome::Failed
                         }
-                    } else {
-                        error!(?r);
                     }
+                } else {
+                    error!(?r);
+                    Outcome::Failed
                 }
-                Err(e) => error!(%e),
+            }
+            Err(e) => {
+                error!(%e);
+                Outcome::Failed
             }
         };
+        METRICS.outcome(&kind, &outcome);
+        outcome
+    }
+}
+
+impl Web for Movie {
+    const KIND: &'static str = "movie";
+    const PATH: &'static str = "movies";
+    const INDEXES: &'static [&'static str] = &["imdb", "source"];
+
+    fn index_keys(&self) -> Vec<Vec<String>> {
+        vec![
+            self.imdb.iter().cloned().collect(),
+            source_urls(&self.source, &self.sources),
+        ]
+    }
+
+    fn title(&self) -> &str {
+        &self.title
+    }
+
+    fn rating(&self) -> &Rating {
+        &self.rating
+    }
+
+    fn search_fields(&self) -> Vec<(&str, u32)> {
+        let mut fields = vec![(self.title.as_str(), 3)];
+        fields.extend(self.alias.iter().map(|s| (s.as_str(), 3)));
+        fields.extend(self.directors.iter().map(|p| (p.name.as_str(), 2)));
+        fields.extend(self.writers.iter().map(|p| (p.name.as_str(), 2)));
+        fields.extend(self.stars.iter().map(|p| (p.name.as_str(), 2)));
+        fields.extend(self.tags.iter().map(|s| (s.as_str(), 2)));
+        fields.extend(self.description.iter().map(|s| (s.as_str(), 1)));
+        fields
+    }
+
+    fn people(&self) -> Vec<(&Person, &'static str)> {
+        let mut people: Vec<_> = self.directors.iter().map(|p| (p, "director")).collect();
+        people.extend(self.writers.iter().map(|p| (p, "writer")));
+        people.extend(self.stars.iter().map(|p| (p, "actor")));
+        people
+    }
+}
+
+impl Web for Book {
+    const KIND: &'static str = "book";
+    const PATH: &'static str = "books";
+    const INDEXES: &'static [&'static str] = &["isbn", "source"];
+
+    fn index_keys(&self) -> Vec<Vec<String>> {
+        vec![
+            self.isbn13.iter().cloned().collect(),
+            source_urls(&self.source, &self.sources),
+        ]
+    }
+
+    fn title(&self) -> &str {
+        &self.title
+    }
+
+    fn rating(&self) -> &Rating {
+        &self.rating
+    }
+
+    fn search_fields(&self) -> Vec<(&str, u32)> {
+        let mut fields = vec![(self.title.as_str(), 3)];
+        fields.extend(self.subtitle.iter().map(|s| (s.as_str(), 3)));
+        fields.extend(self.original_title.iter().map(|s| (s.as_str(), 3)));
+        fields.extend(self.authors.iter().map(|p| (p.name.as_str(), 2)));
+        fields.extend(self.translators.iter().map(|p| (p.name.as_str(), 2)));
+        fields.extend(self.tags.iter().map(|s| (s.as_str(), 2)));
+        fields.extend(self.description.iter().map(|s| (s.as_str(), 1)));
+        fields.extend(self.content.iter().map(|s| (s.as_str(), 1)));
+        fields
+    }
+
+    fn people(&self) -> Vec<(&Person, &'static str)> {
+        let mut people: Vec<_> = self.authors.iter().map(|p| (p, "author")).collect();
+        people.extend(self.translators.iter().map(|p| (p, "translator")));
+        people
+    }
+}
+
+impl Web for Album {
+    const KIND: &'static str = "album";
+    const PATH: &'static str = "music/album";
+    const INDEXES: &'static [&'static str] = &["barcode", "source"];
+
+    fn index_keys(&self) -> Vec<Vec<String>> {
+        vec![
+            self.code
+                .iter()
+                .map(|code| normalize_barcode(code))
+                .collect(),
+            source_urls(&self.source, &self.sources),
+        ]
+    }
+
+    fn title(&self) -> &str {
+        &self.title
+    }
+
+    fn rating(&self) -> &Rating {
+        &self.rating
+    }
+
+    fn search_fields(&self) -> Vec<(&str, u32)> {
+        let mut fields = vec![(self.title.as_str(), 3)];
+        fields.extend(self.artists.iter().map(|p| (p.name.as_str(), 2)));
+        fields.extend(self.tags.iter().map(|s| (s.as_str(), 2)));
+        fields.extend(self.tracks.iter().map(|t| (t.title.as_str(), 1)));
+        fields.extend(self.description.iter().map(|s| (s.as_str(), 1)));
+        fields.extend(self.content.iter().map(|s| (s.as_str(), 1)));
+        fields
+    }
+
+    fn people(&self) -> Vec<(&Person, &'static str)> {
+        self.artists.iter().map(|p| (p, "artist")).collect()
+    }
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Http(reqwest::Error),
+    Io(std::io::Error),
+    /// the body is larger than the allowed size
+    TooLarge(u64),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Http(e) => write!(f, "{e}"),
+            SaveError::Io(e) => write!(f, "{e}"),
+            SaveError::TooLarge(size) => write!(f, "body exceeds {size} bytes"),
+        }
+    }
+}
+
+impl From<reqwest::Error> for SaveError {
+    fn from(e: reqwest::Error) -> Self {
+        SaveError::Http(e)
+    }
+}
+
+impl From<std::io::Error> for SaveError {
+    fn from(e: std::io::Error) -> Self {
+        SaveError::Io(e)
+    }
+}
+
+/// Stream the response body to `fpath` chunk by chunk, returning the bytes written.
+///
+/// The body goes to `{fpath}.part` first and is renamed when complete, so an interrupted
+/// or oversized download never leaves a truncated file at `fpath`.
+pub async fn save_body(mut r: Response, fpath: &str, max_size: u64) -> Result<u64, SaveError> {
+    if let Some(len) = r.content_length() {
+        if len > max_size {
+            return Err(SaveError::TooLarge(max_size));
+        }
+    }
+
+    let part_path = format!("{fpath}.part");
+    let mut dest = File::create(&part_path).await?;
+    let mut written = 0;
+    let res: Result<(), SaveError> = async {
+        while let Some(chunk) = r.chunk().await? {
+            written += chunk.len() as u64;
+            if written > max_size {
+                return Err(SaveError::TooLarge(max_size));
+            }
+            dest.write_all(&chunk).await?;
+        }
+        dest.flush().await?;
+        Ok(())
+    }
+    .await;
+
+    match res {
+        Ok(()) => {
+            tokio::fs::rename(&part_path, fpath).await?;
+            Ok(written)
+        }
+        Err(e) => {
+            drop(dest);
+            tokio::fs::remove_file(&part_path).await.ok();
+            Err(e)
+        }
+    }
+}
+
+/// `source` and the urls of `sources`, for the `source` index
+fn source_urls(source: &Option<String>, sources: &[ExternalSource]) -> Vec<String> {
+    let mut urls: Vec<String> = source.iter().cloned().collect();
+    for s in sources {
+        if !urls.contains(&s.url) {
+            urls.push(s.url.clone());
+        }
+    }
+    urls
+}
+
+/// title of the stored item `id` of `kind` (`movie`, `book` or `album`)
+pub fn title(db: &Db, kind: &str, id: u32) -> Option<String> {
+    fn title_of<T: Web>(db: &Db, id: u32) -> Option<String> {
+        let v = db
+            .open_tree(format!("{}s", T::KIND))
+            .unwrap()
+            .get(u32_to_ivec(id))
+            .unwrap()?;
+        decode::<T>(&v).map(|one| one.title().to_owned())
+    }
+    match kind {
+        Movie::KIND => title_of::<Movie>(db, id),
+        Book::KIND => title_of::<Book>(db, id),
+        Album::KIND => title_of::<Album>(db, id),
+        _ => None,
     }
 }
 
-impl Web for Movie {}
-impl Web for Book {}
-impl Web for Album {}
+/// key of `id` in an index: `key`, a 0 byte and the id, so several items can share `key`
+pub fn index_key(key: &str, id: u32) -> Vec<u8> {
+    let mut k = Vec::with_capacity(key.len() + 5);
+    k.extend_from_slice(key.as_bytes());
+    k.push(0);
+    k.extend_from_slice(&id.to_be_bytes());
+    k
+}
+
+/// key and id of an [index_key], `None` if `k` is not one
+pub fn split_index_key(k: &[u8]) -> Option<(&[u8], u32)> {
+    let (key, id) = k.split_at_checked(k.len().checked_sub(5)?)?;
+    let id = id.strip_prefix(&[0])?;
+    Some((key, u32::from_be_bytes(id.try_into().ok()?)))
+}
 
 /// convert `u32` to [IVec]
-fn u32_to_ivec(number: u32) -> IVec {
+pub fn u32_to_ivec(number: u32) -> IVec {
     IVec::from(number.to_be_bytes().to_vec())
 }
 
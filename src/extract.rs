@@ -1,192 +1,415 @@
+use crate::{
+    isbn::to_isbn13,
+    labels::{field_key, Locale},
+    normalize::{
+        parse_count, parse_date, parse_duration, parse_genres, parse_minutes, parse_pages,
+        parse_price, parse_score, PartialDate, Price,
+    },
+    sources::ExternalSource,
+    text::{block, plain, sanitize},
+    tracks::{from_page, numbered, Track},
+};
 use bincode::{Decode, Encode};
 use once_cell::sync::Lazy;
-use scraper::{Html, Selector};
+use scraper::{ElementRef, Html, Selector};
+use serde::Serialize;
+use std::fmt;
 
-const TITLE_STR: &str = r#"title"#;
-const H5_STR: &str = r#"h5"#;
 const A_STR: &str = r#"a"#;
-const DIV_STR: &str = r#"div"#;
 const SPAN_STR: &str = r#"span"#;
 
-const COVER_STR: &str = r#"a[class="entity-detail__img-origin"]"#;
-const DETAILS_STR: &str = r#"div[class="entity-detail__fields"]"#;
-const TAG_STR: &str = r#"span[class="tag-collection__tag"]"#;
-const DESC_STR: &str = r#"p[class="entity-desc__content"]"#;
-
 const DIRECTOR_STR: &str = r#"span[class="director"]"#;
 const PLAYWRIGHT_STR: &str = r#"span[class="playwright"]"#;
 const ACTOR_STR: &str = r#"span[class="actor"]"#;
 
 const ARTIST_STR: &str = r#"span[class="artist"]"#;
 const COMPANY_STR: &str = r#"span[class="company"]"#;
-const TRACK_STR: &str = r#"span[class="track-carousel__track-title"]"#;
 
-static TITLE: Lazy<Selector> = Lazy::new(|| Selector::parse(TITLE_STR).unwrap());
-static H5: Lazy<Selector> = Lazy::new(|| Selector::parse(H5_STR).unwrap());
 static A: Lazy<Selector> = Lazy::new(|| Selector::parse(A_STR).unwrap());
-static DIV: Lazy<Selector> = Lazy::new(|| Selector::parse(DIV_STR).unwrap());
 static SPAN: Lazy<Selector> = Lazy::new(|| Selector::parse(SPAN_STR).unwrap());
 
-static COVER: Lazy<Selector> = Lazy::new(|| Selector::parse(COVER_STR).unwrap());
-static DETAILS: Lazy<Selector> = Lazy::new(|| Selector::parse(DETAILS_STR).unwrap());
-static TAG: Lazy<Selector> = Lazy::new(|| Selector::parse(TAG_STR).unwrap());
-static DESC: Lazy<Selector> = Lazy::new(|| Selector::parse(DESC_STR).unwrap());
-
 static DIRECTOR: Lazy<Selector> = Lazy::new(|| Selector::parse(DIRECTOR_STR).unwrap());
 static PLAYWRIGHT: Lazy<Selector> = Lazy::new(|| Selector::parse(PLAYWRIGHT_STR).unwrap());
 static ACTOR: Lazy<Selector> = Lazy::new(|| Selector::parse(ACTOR_STR).unwrap());
 
 static ARTIST: Lazy<Selector> = Lazy::new(|| Selector::parse(ARTIST_STR).unwrap());
 static COMPANY: Lazy<Selector> = Lazy::new(|| Selector::parse(COMPANY_STR).unwrap());
-static TRACK: Lazy<Selector> = Lazy::new(|| Selector::parse(TRACK_STR).unwrap());
 
-#[derive(Debug, Encode, Decode)]
-pub struct Book {
-    pub title: String,
-    pub cover: Option<String>,
-    pub source: Option<String>,
-    pub isbn: Option<String>,
-    pub authors: Vec<String>,
-    pub publisher: Option<String>,
-    pub subtitle: Option<String>,
-    pub translators: Vec<String>,
-    pub original_title: Option<String>,
-    pub language: Option<String>,
-    pub pub_time: Option<String>,
-    pub bookformat: Option<String>,
-    pub price: Option<String>,
-    pub pages: Option<String>,
-    pub other_info: Option<String>,
-    pub tags: Vec<String>,
-    pub description: Option<String>,
-    pub content: Option<String>,
+/// selectors of one neodb page template
+pub struct Layout {
+    pub name: &'static str,
+    /// only found on pages of this layout
+    marker: Selector,
+    title: Selector,
+    /// the title is the text after it, eg. `NeoDB - 书籍 | 三体`, or the whole text if `None`
+    title_sep: Option<&'static str>,
+    cover: Selector,
+    /// attribute of `cover` with the url
+    cover_attr: &'static str,
+    /// links to the sources of the item, the one it was imported from first
+    source: Selector,
+    /// rows of the details, in page order
+    fields: Selector,
+    tag: Selector,
+    /// the description, then the table of contents or the track list as text
+    desc: Selector,
+    /// the track list of each disc
+    track_list: Selector,
+    /// in a track list
+    track: Selector,
+    /// in a track, the whole track if missing
+    track_title: Selector,
+    /// in a track, eg. `4:44`
+    track_duration: Selector,
+    /// the average rating, eg. `8.8`
+    score: Selector,
+    /// the number of ratings, eg. `(1432人评分)`
+    score_count: Selector,
+    /// the number of marks, eg. `(2817)`
+    marks: Selector,
+    /// the number of reviews
+    reviews: Selector,
 }
 
-impl From<&str> for Book {
-    fn from(html: &str) -> Self {
-        let fragment = Html::parse_fragment(html);
+fn sel(s: &str) -> Selector {
+    Selector::parse(s).unwrap()
+}
 
-        let title = fragment
-            .select(&TITLE)
-            .next()
-            .unwrap()
-            .inner_html()
-            .rsplit_once("| ")
-            .unwrap()
-            .1
-            .to_owned();
-
-        let cover = fragment
-            .select(&COVER)
-            .next()
-            .unwrap()
-            .value()
-            .attr("href")
-            .map(|s| s.to_owned())
-            .and_then(empty2none);
+/// the template until 2023, `entity-detail__*`
+pub static OLD: Lazy<Layout> = Lazy::new(|| Layout {
+    name: "old",
+    marker: sel(r#"div[class="entity-detail__fields"]"#),
+    title: sel("title"),
+    title_sep: Some("| "),
+    cover: sel(r#"a[class="entity-detail__img-origin"]"#),
+    cover_attr: "href",
+    source: sel(r#"h5[class="entity-detail__title"] a"#),
+    fields: sel(r#"div[class="entity-detail__fields"] > div:not(.entity-detail__rating)"#),
+    tag: sel(r#"span[class="tag-collection__tag"]"#),
+    desc: sel(r#"p[class="entity-desc__content"]"#),
+    track_list: sel(r#"div[class="track-carousel__content"]"#),
+    track: sel(r#"a[class="track-carousel__track"]"#),
+    track_title: sel(r#"span[class="track-carousel__track-title"]"#),
+    track_duration: sel(r#"span[class="track-carousel__track-duration"]"#),
+    score: sel(r#"span[class="entity-detail__rating-score"]"#),
+    score_count: sel(r#"div[class="entity-detail__rating"] > small"#),
+    marks: sel(r#"div[class="entity-marks"] h5 small"#),
+    reviews: sel(r#"div[class="entity-reviews"] h5 small"#),
+});
+
+/// the current template, `#item-title`, `#item-cover`, `#item-metadata`
+pub static NEW: Lazy<Layout> = Lazy::new(|| Layout {
+    name: "new",
+    marker: sel("div#item-metadata"),
+    title: sel("div#item-title > h1"),
+    title_sep: None,
+    cover: sel("div#item-cover img"),
+    cover_attr: "src",
+    source: sel("div#item-title .site-list a"),
+    fields: sel("div#item-metadata section.details > div"),
+    tag: sel("div.tag-list span"),
+    desc: sel("div.markdown-content"),
+    track_list: sel("ol.track-list"),
+    track: sel("li"),
+    track_title: sel(".track-title"),
+    track_duration: sel(".track-duration"),
+    score: sel("section.rating hgroup h3"),
+    score_count: sel("section.rating hgroup small"),
+    marks: sel("section#item-marks h5 small"),
+    reviews: sel("section#item-reviews h5 small"),
+});
+
+impl Layout {
+    /// The layout of `html`, the old one if none matches.
+    pub fn detect(html: &Html) -> &'static Layout {
+        [&*NEW, &*OLD]
+            .into_iter()
+            .find(|layout| html.select(&layout.marker).next().is_some())
+            .unwrap_or(&OLD)
+    }
+
+    fn title(&self, html: &Html) -> Result<String, ParseError> {
+        let ele = html.select(&self.title).next().ok_or(ParseError("title"))?;
+        let title = match self.title_sep {
+            Some(sep) => plain(&ele)
+                .rsplit_once(sep.trim())
+                .ok_or(ParseError("title"))?
+                .1
+                .trim()
+                .to_owned(),
+            // the text before any `<small>` year or subtitle
+            None => ele
+                .text()
+                .next()
+                .unwrap_or_default()
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" "),
+        };
+        empty2none(title).ok_or(ParseError("title"))
+    }
 
-        let source = fragment
-            .select(&H5)
-            .next()
-            .unwrap()
-            .select(&A)
-            .next()
-            .unwrap()
+    /// `None` if the page has no cover element, as for items without a cover
+    fn cover(&self, html: &Html) -> Option<String> {
+        html.select(&self.cover)
+            .next()?
             .value()
-            .attr("href")
+            .attr(self.cover_attr)
             .map(|s| s.to_owned())
-            .and_then(empty2none);
-
-        // details
-        let mut details = fragment.select(&DETAILS);
-
-        // first parts
-        let mut div_ele = details.next().unwrap().select(&DIV).skip(1);
+            .and_then(empty2none)
+    }
 
-        let isbn = div_ele
-            .next()
-            .map(|ele| ele.inner_html().trim_start_matches("ISBN：").to_owned())
-            .and_then(empty2none);
+    /// the link of the first source, and all the sources
+    fn source(&self, html: &Html) -> Result<(Option<String>, Vec<ExternalSource>), ParseError> {
+        let mut links = html
+            .select(&self.source)
+            .map(|a| a.value().attr("href").unwrap_or_default().trim())
+            .peekable();
+        let first = links.peek().ok_or(ParseError("source"))?.to_string();
+        let mut sources: Vec<ExternalSource> = vec![];
+        for source in links.filter_map(ExternalSource::parse) {
+            if !sources.iter().any(|s| s.url == source.url) {
+                sources.push(source);
+            }
+        }
+        Ok((empty2none(first), sources))
+    }
 
-        let authors = div_ele
-            .next()
-            .unwrap()
-            .select(&SPAN)
-            .map(|ele| ele.inner_html())
-            .collect();
+    fn fields<'a>(&'a self, html: &'a Html) -> impl Iterator<Item = ElementRef<'a>> + 'a {
+        html.select(&self.fields)
+    }
 
-        let publisher = div_ele
-            .next()
-            .map(|ele| ele.inner_html().trim_start_matches("出版社：").to_owned())
-            .and_then(empty2none);
+    fn tags(&self, html: &Html) -> Vec<String> {
+        html.select(&self.tag)
+            .filter_map(|ele| ele.select(&A).next().map(|a| plain(&a)))
+            .collect()
+    }
 
-        let subtitle = div_ele
-            .next()
-            .map(|ele| ele.inner_html().trim_start_matches("副标题：").to_owned())
-            .and_then(empty2none);
+    fn rating(&self, html: &Html) -> Rating {
+        let text = |selector| html.select(selector).next().map(|ele| plain(&ele));
+        let score = text(&self.score).as_deref().and_then(parse_score);
+        Rating {
+            score,
+            // `0 个评分` next to no score
+            count: text(&self.score_count)
+                .as_deref()
+                .and_then(parse_count)
+                .filter(|_| score.is_some()),
+            marks: text(&self.marks).as_deref().and_then(parse_count),
+            reviews: text(&self.reviews).as_deref().and_then(parse_count),
+        }
+    }
 
-        let translators = div_ele
-            .next()
-            .unwrap()
-            .select(&SPAN)
-            .map(|ele| ele.inner_html())
+    /// the tracks of the track elements, one list for each disc
+    fn tracks(&self, html: &Html) -> Vec<Track> {
+        let discs = html
+            .select(&self.track_list)
+            .map(|list| {
+                list.select(&self.track)
+                    .map(|track| {
+                        let title = match track.select(&self.track_title).next() {
+                            Some(title) => plain(&title),
+                            None => plain(&track),
+                        };
+                        let duration = track
+                            .select(&self.track_duration)
+                            .next()
+                            .and_then(|ele| parse_duration(&plain(&ele)));
+                        (title, duration)
+                    })
+                    .filter(|(title, _)| !title.is_empty())
+                    .collect()
+            })
             .collect();
+        numbered(discs)
+    }
 
-        let original_title = div_ele
-            .next()
-            .map(|ele| ele.inner_html().trim_start_matches("原作名：").to_owned())
-            .and_then(empty2none);
+    /// the description and the contents, as text and as sanitized html
+    fn desc(&self, html: &Html) -> (Desc, Desc) {
+        let mut descs = html.select(&self.desc).map(|ele| Desc {
+            text: empty2none(block(&ele)),
+            html: empty2none(sanitize(&ele)),
+        });
+        (
+            descs.next().unwrap_or_default(),
+            descs.next().unwrap_or_default(),
+        )
+    }
+}
 
-        let language = div_ele
-            .next()
-            .map(|ele| ele.inner_html().trim_start_matches("语言：").to_owned())
-            .and_then(empty2none);
+/// the rating of an item and how many marked and reviewed it, when the page was fetched;
+/// see [crate::ratings] for the former ones
+#[derive(Debug, Clone, Default, PartialEq, Encode, Decode, Serialize)]
+pub struct Rating {
+    /// average, out of 10
+    pub score: Option<f64>,
+    /// number of ratings
+    pub count: Option<u32>,
+    pub marks: Option<u32>,
+    pub reviews: Option<u32>,
+}
 
-        let pub_time = div_ele
-            .next()
-            .map(|ele| ele.inner_html().trim_start_matches("出版时间：").to_owned())
-            .and_then(empty2none);
+impl Rating {
+    pub fn is_empty(&self) -> bool {
+        *self == Rating::default()
+    }
+}
 
-        // second parts
-        let mut div_ele = details.next().unwrap().select(&DIV);
+/// a person credited on an item, with the link to their page if there is one, so
+/// namesakes are told apart
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Serialize)]
+pub struct Person {
+    pub name: String,
+    /// link to the person page, eg. `/people/1a2b3c/`
+    pub url: Option<String>,
+    /// last segment of `url`, eg. `1a2b3c`
+    pub id: Option<String>,
+}
 
-        let bookformat = div_ele
-            .next()
-            .map(|ele| ele.inner_html().trim_start_matches("装帧：").to_owned())
-            .and_then(empty2none);
+impl Person {
+    /// a person known only by name, eg. stored before links were kept
+    pub fn named(name: String) -> Self {
+        Person {
+            name,
+            url: None,
+            id: None,
+        }
+    }
 
-        let price = div_ele
-            .next()
-            .map(|ele| ele.inner_html().trim_start_matches("定价：").to_owned())
+    /// the text of `ele`, and the link of `ele` or of the first link in it
+    fn from_ele(ele: &ElementRef) -> Self {
+        let link = if ele.value().name() == "a" {
+            Some(*ele)
+        } else {
+            ele.select(&A).next()
+        };
+        let url = link
+            .and_then(|a| a.value().attr("href"))
+            .map(|href| href.trim().to_owned())
             .and_then(empty2none);
+        let id = url.as_deref().and_then(url_id);
+        Person {
+            name: plain(ele),
+            url,
+            id,
+        }
+    }
+}
 
-        let pages = div_ele
-            .next()
-            .map(|ele| ele.inner_html().trim_start_matches("页数：").to_owned())
-            .and_then(empty2none);
+/// `1a2b3c` of `/people/1a2b3c/?from=item`, `None` for a bare site
+fn url_id(url: &str) -> Option<String> {
+    let path = url.split(['?', '#']).next()?.trim_end_matches('/');
+    let id = path.rsplit('/').next()?;
+    if id.is_empty() || id.contains(['.', ':']) {
+        None
+    } else {
+        Some(id.to_owned())
+    }
+}
 
-        let other_info = div_ele
-            .next()
-            .map(|ele| ele.inner_html().trim().to_owned())
-            .and_then(empty2none);
+/// a description as text and as sanitized html
+#[derive(Default)]
+struct Desc {
+    text: Option<String>,
+    html: Option<String>,
+}
 
-        let tags = fragment
-            .select(&TAG)
-            .map(|ele| ele.select(&A).next().unwrap().inner_html())
-            .collect();
+#[derive(Debug, Encode, Decode, Serialize)]
+pub struct Book {
+    pub title: String,
+    pub cover: Option<String>,
+    pub source: Option<String>,
+    pub isbn: Option<String>,
+    pub authors: Vec<Person>,
+    pub publisher: Option<String>,
+    pub subtitle: Option<String>,
+    pub translators: Vec<Person>,
+    pub original_title: Option<String>,
+    pub language: Option<String>,
+    pub pub_time: Option<String>,
+    pub bookformat: Option<String>,
+    pub price: Option<String>,
+    pub pages: Option<String>,
+    pub other_info: Option<String>,
+    pub tags: Vec<String>,
+    pub description: Option<String>,
+    pub content: Option<String>,
+    /// parsed from `price`
+    pub price_value: Option<Price>,
+    /// parsed from `pages`
+    pub page_count: Option<u32>,
+    /// parsed from `pub_time`
+    pub pub_date: Option<PartialDate>,
+    /// `isbn` as a valid ISBN-13
+    pub isbn13: Option<String>,
+    /// `isbn` is present but fails the checksum
+    pub isbn_invalid: bool,
+    /// `description` as sanitized html
+    pub description_html: Option<String>,
+    /// `content` as sanitized html
+    pub content_html: Option<String>,
+    pub rating: Rating,
+    /// every source of the item, `source` included
+    pub sources: Vec<ExternalSource>,
+}
 
-        let mut div_ele = fragment.select(&DESC);
-        let description = div_ele
-            .next()
-            .map(|ele| ele.inner_html().trim().to_owned())
-            .and_then(empty2none);
+/// keys of the [Book] fields, the other rows go to `other_info`
+const BOOK_FIELDS: [&str; 11] = [
+    "isbn",
+    "author",
+    "publisher",
+    "subtitle",
+    "translator",
+    "original_title",
+    "language",
+    "pub_time",
+    "binding",
+    "price",
+    "pages",
+];
+
+impl TryFrom<&str> for Book {
+    type Error = ParseError;
+
+    fn try_from(html: &str) -> Result<Self, Self::Error> {
+        let fragment = Html::parse_fragment(html);
+        let layout = Layout::detect(&fragment);
 
-        let content = div_ele
-            .next()
-            .map(|ele| ele.inner_html().trim().to_owned())
-            .and_then(empty2none);
+        let title = layout.title(&fragment)?;
+        let cover = layout.cover(&fragment);
+        let (source, sources) = layout.source(&fragment)?;
 
-        Book {
+        // details
+        let fields = Fields::new(layout.fields(&fragment), Locale::detect(&fragment))?;
+
+        let isbn = fields.text("isbn");
+        let authors = fields.people("author");
+        let publisher = fields.text("publisher");
+        let subtitle = fields.text("subtitle");
+        let translators = fields.people("translator");
+        let original_title = fields.text("original_title");
+        let language = fields.text("language");
+        let pub_time = fields.text("pub_time");
+        let bookformat = fields.text("binding");
+        let price = fields.text("price");
+        let pages = fields.text("pages");
+        // eg. the series, which has no field of its own
+        let other_info = fields.others(&BOOK_FIELDS);
+
+        let tags = layout.tags(&fragment);
+        let rating = layout.rating(&fragment);
+
+        let (desc, contents) = layout.desc(&fragment);
+        let (description, description_html) = (desc.text, desc.html);
+        let (content, content_html) = (contents.text, contents.html);
+
+        let price_value = price.as_deref().and_then(parse_price);
+        let page_count = pages.as_deref().and_then(parse_pages);
+        let pub_date = pub_time.as_deref().and_then(parse_date);
+        let isbn13 = isbn.as_deref().and_then(to_isbn13);
+        let isbn_invalid = isbn.is_some() && isbn13.is_none();
+
+        Ok(Book {
             title,
             cover,
             source,
@@ -205,19 +428,28 @@ impl From<&str> for Book {
             tags,
             description,
             content,
-        }
+            price_value,
+            page_count,
+            pub_date,
+            isbn13,
+            isbn_invalid,
+            description_html,
+            content_html,
+            rating,
+            sources,
+        })
     }
 }
 
-#[derive(Debug, Encode, Decode)]
+#[derive(Debug, Encode, Decode, Serialize)]
 pub struct Movie {
     pub title: String,
     pub cover: Option<String>,
     pub source: Option<String>,
     pub imdb: Option<String>,
-    pub directors: Vec<String>,
-    pub writers: Vec<String>,
-    pub stars: Vec<String>,
+    pub directors: Vec<Person>,
+    pub writers: Vec<Person>,
+    pub stars: Vec<Person>,
     pub genres: Vec<String>,
     pub countries: Vec<String>,
     pub languages: Vec<String>,
@@ -229,139 +461,71 @@ pub struct Movie {
     pub alias: Vec<String>,
     pub tags: Vec<String>,
     pub description: Option<String>,
+    /// parsed from `runtime`
+    pub runtime_minutes: Option<u32>,
+    /// parsed from `episode_runtime`
+    pub episode_runtime_minutes: Option<u32>,
+    /// parsed from `release_dates`, same order
+    pub release_dates_parsed: Vec<Option<PartialDate>>,
+    /// `description` as sanitized html
+    pub description_html: Option<String>,
+    pub rating: Rating,
+    /// every source of the item, `source` included
+    pub sources: Vec<ExternalSource>,
 }
 
-impl From<&str> for Movie {
-    fn from(html: &str) -> Self {
-        let fragment = Html::parse_fragment(html);
+impl TryFrom<&str> for Movie {
+    type Error = ParseError;
 
-        let title = fragment
-            .select(&TITLE)
-            .next()
-            .unwrap()
-            .inner_html()
-            .rsplit_once("| ")
-            .unwrap()
-            .1
-            .to_owned();
-
-        let cover = fragment
-            .select(&COVER)
-            .next()
-            .unwrap()
-            .value()
-            .attr("href")
-            .map(|s| s.to_owned())
-            .and_then(empty2none);
+    fn try_from(html: &str) -> Result<Self, Self::Error> {
+        let fragment = Html::parse_fragment(html);
+        let layout = Layout::detect(&fragment);
 
-        let source = fragment
-            .select(&H5)
-            .next()
-            .unwrap()
-            .select(&A)
-            .next()
-            .unwrap()
-            .value()
-            .attr("href")
-            .map(|s| s.to_owned())
-            .and_then(empty2none);
+        let title = layout.title(&fragment)?;
+        let cover = layout.cover(&fragment);
+        let (source, sources) = layout.source(&fragment)?;
 
         // details
-        let mut details = fragment.select(&DETAILS);
-
-        // first parts
-        let mut div_ele = details.next().unwrap().select(&DIV).skip(1);
+        let fields = Fields::new(layout.fields(&fragment), Locale::detect(&fragment))?;
 
-        let imdb = div_ele
-            .next()
-            .and_then(|ele| ele.select(&A).next().map(|a| a.inner_html()))
-            .and_then(empty2none);
+        let imdb = fields.link_text("imdb");
 
         let directors = fragment
             .select(&DIRECTOR)
-            .map(|ele| ele.inner_html())
+            .map(|ele| Person::from_ele(&ele))
             .collect();
 
         let writers = fragment
             .select(&PLAYWRIGHT)
-            .map(|ele| ele.inner_html())
+            .map(|ele| Person::from_ele(&ele))
             .collect();
 
         let stars = fragment
             .select(&ACTOR)
-            .map(|ele| ele.inner_html())
+            .map(|ele| Person::from_ele(&ele))
             .collect();
 
-        let genres = div_ele
-            .nth(3)
-            .unwrap()
-            .select(&SPAN)
-            .map(|ele| ele.inner_html())
-            .collect();
+        let genres = fields.spans("genre");
+        let countries = fields.spans("country");
+        let languages = fields.spans("language");
+        let runtime = fields.text("runtime");
+        let season = fields.text("season");
+        let episode = fields.text("episodes");
+        let episode_runtime = fields.text("episode_runtime");
+        let release_dates: Vec<String> = fields.spans("release_date");
+        let alias = fields.spans("alias");
 
-        let countries = div_ele
-            .next()
-            .unwrap()
-            .select(&SPAN)
-            .map(|ele| ele.inner_html())
-            .collect();
+        let tags = layout.tags(&fragment);
+        let rating = layout.rating(&fragment);
 
-        let languages = div_ele
-            .next()
-            .unwrap()
-            .select(&SPAN)
-            .map(|ele| ele.inner_html())
-            .collect();
+        let (desc, _) = layout.desc(&fragment);
+        let (description, description_html) = (desc.text, desc.html);
 
-        // second parts
-        let mut div_ele = details.next().unwrap().select(&DIV);
+        let runtime_minutes = runtime.as_deref().and_then(parse_minutes);
+        let episode_runtime_minutes = episode_runtime.as_deref().and_then(parse_minutes);
+        let release_dates_parsed = release_dates.iter().map(|d| parse_date(d)).collect();
 
-        let runtime = div_ele
-            .next()
-            .map(|ele| ele.inner_html().trim_start_matches("片长：").to_owned())
-            .and_then(empty2none);
-
-        let season = div_ele
-            .next()
-            .map(|ele| ele.inner_html().trim_start_matches("季数：").to_owned())
-            .and_then(empty2none);
-
-        let episode = div_ele
-            .next()
-            .map(|ele| ele.inner_html().trim_start_matches("集数：").to_owned())
-            .and_then(empty2none);
-
-        let episode_runtime = div_ele
-            .next()
-            .map(|ele| ele.inner_html().trim_start_matches("单集长度：").to_owned())
-            .and_then(empty2none);
-
-        let release_dates = div_ele
-            .next()
-            .unwrap()
-            .select(&SPAN)
-            .map(|ele| ele.inner_html())
-            .collect();
-
-        let alias = div_ele
-            .next()
-            .unwrap()
-            .select(&SPAN)
-            .map(|ele| ele.inner_html())
-            .collect();
-
-        let tags = fragment
-            .select(&TAG)
-            .map(|ele| ele.select(&A).next().unwrap().inner_html())
-            .collect();
-
-        let mut div_ele = fragment.select(&DESC);
-        let description = div_ele
-            .next()
-            .map(|ele| ele.inner_html().trim().to_owned())
-            .and_then(empty2none);
-
-        Movie {
+        Ok(Movie {
             title,
             cover,
             source,
@@ -380,16 +544,22 @@ impl From<&str> for Movie {
             alias,
             tags,
             description,
-        }
+            runtime_minutes,
+            episode_runtime_minutes,
+            release_dates_parsed,
+            description_html,
+            rating,
+            sources,
+        })
     }
 }
 
-#[derive(Debug, Encode, Decode)]
+#[derive(Debug, Encode, Decode, Serialize)]
 pub struct Album {
     pub title: String,
     pub cover: Option<String>,
     pub source: Option<String>,
-    pub artists: Vec<String>,
+    pub artists: Vec<Person>,
     pub companies: Vec<String>,
     pub pub_time: Option<String>,
     pub genre: Option<String>,
@@ -399,135 +569,60 @@ pub struct Album {
     pub tags: Vec<String>,
     pub description: Option<String>,
     pub content: Option<String>,
-    pub tracks: Vec<String>,
+    pub tracks: Vec<Track>,
+    /// `description` as sanitized html
+    pub description_html: Option<String>,
+    /// `content` as sanitized html
+    pub content_html: Option<String>,
+    pub rating: Rating,
+    /// every source of the item, `source` included
+    pub sources: Vec<ExternalSource>,
+    /// parsed from `pub_time`
+    pub pub_date: Option<PartialDate>,
+    /// `genre` split and named the same across languages, eg. `Rock` for `摇滚`
+    pub genres: Vec<String>,
 }
 
-impl From<&str> for Album {
-    fn from(html: &str) -> Self {
-        let fragment = Html::parse_fragment(html);
+impl TryFrom<&str> for Album {
+    type Error = ParseError;
 
-        let title = fragment
-            .select(&TITLE)
-            .next()
-            .unwrap()
-            .inner_html()
-            .rsplit_once("| ")
-            .unwrap()
-            .1
-            .to_owned();
-
-        let cover = fragment
-            .select(&COVER)
-            .next()
-            .unwrap()
-            .value()
-            .attr("href")
-            .map(|s| s.to_owned())
-            .and_then(empty2none);
+    fn try_from(html: &str) -> Result<Self, Self::Error> {
+        let fragment = Html::parse_fragment(html);
+        let layout = Layout::detect(&fragment);
 
-        let source = fragment
-            .select(&H5)
-            .next()
-            .unwrap()
-            .select(&A)
-            .next()
-            .unwrap()
-            .value()
-            .attr("href")
-            .map(|s| s.to_owned())
-            .and_then(empty2none);
+        let title = layout.title(&fragment)?;
+        let cover = layout.cover(&fragment);
+        let (source, sources) = layout.source(&fragment)?;
 
         // details
-        let mut details = fragment.select(&DETAILS);
-
-        // first parts
-        let mut div_ele = details.next().unwrap().select(&DIV).skip(1);
+        let fields = Fields::new(layout.fields(&fragment), Locale::detect(&fragment))?;
 
         let artists = fragment
             .select(&ARTIST)
-            .map(|ele| ele.inner_html())
+            .map(|ele| Person::from_ele(&ele))
             .collect();
 
-        let companies = fragment
-            .select(&COMPANY)
-            .map(|ele| ele.inner_html())
-            .collect();
-
-        let pub_time = div_ele
-            .nth(2)
-            .map(|ele| {
-                ele.inner_html()
-                    .trim()
-                    .trim_start_matches("发行日期：")
-                    .to_owned()
-            })
-            .and_then(empty2none);
-
-        let genre = div_ele
-            .nth(1)
-            .map(|ele| {
-                ele.inner_html()
-                    .trim()
-                    .trim_start_matches("流派：")
-                    .to_owned()
-            })
-            .and_then(empty2none);
-
-        // second parts
-        let mut div_ele = details.next().unwrap().select(&DIV);
-
-        let medium = div_ele
-            .next()
-            .map(|ele| {
-                ele.inner_html()
-                    .trim()
-                    .trim_start_matches("介质：")
-                    .to_owned()
-            })
-            .and_then(empty2none);
+        let companies = fragment.select(&COMPANY).map(|ele| plain(&ele)).collect();
 
-        let code = div_ele
-            .next()
-            .map(|ele| {
-                ele.inner_html()
-                    .trim()
-                    .trim_start_matches("条形码：")
-                    .to_owned()
-            })
-            .and_then(empty2none);
+        let pub_time = fields.text("release_date");
+        let genre = fields.text("genre");
+        let medium = fields.text("medium");
+        let code = fields.text("barcode");
+        let format = fields.text("album_type");
 
-        let format = div_ele
-            .next()
-            .map(|ele| {
-                ele.inner_html()
-                    .trim()
-                    .trim_start_matches("专辑类型：")
-                    .to_owned()
-            })
-            .and_then(empty2none);
+        let tags = layout.tags(&fragment);
+        let rating = layout.rating(&fragment);
 
-        let tags = fragment
-            .select(&TAG)
-            .map(|ele| ele.select(&A).next().unwrap().inner_html())
-            .collect();
+        let (desc, contents) = layout.desc(&fragment);
+        let (description, description_html) = (desc.text, desc.html);
+        let (content, content_html) = (contents.text, contents.html);
 
-        let mut div_ele = fragment.select(&DESC);
-        let description = div_ele
-            .next()
-            .map(|ele| ele.inner_html().trim().to_owned())
-            .and_then(empty2none);
+        let tracks = from_page(content.as_deref(), layout.tracks(&fragment));
 
-        let content = div_ele
-            .next()
-            .map(|ele| ele.inner_html().trim().to_owned())
-            .and_then(empty2none);
+        let pub_date = pub_time.as_deref().and_then(parse_date);
+        let genres = genre.as_deref().map(parse_genres).unwrap_or_default();
 
-        let tracks = fragment
-            .select(&TRACK)
-            .map(|ele| ele.inner_html().trim().to_owned())
-            .collect();
-
-        Album {
+        Ok(Album {
             title,
             cover,
             source,
@@ -542,7 +637,111 @@ impl From<&str> for Album {
             description,
             content,
             tracks,
+            description_html,
+            content_html,
+            rating,
+            sources,
+            pub_date,
+            genres,
+        })
+    }
+}
+
+/// rows of the details by field key, eg. `publisher` of `出版社：上海译文出版社` or
+/// `Publisher: 上海译文出版社`, see [crate::labels]
+struct Fields<'a> {
+    rows: Vec<(Option<&'static str>, ElementRef<'a>)>,
+}
+
+impl<'a> Fields<'a> {
+    fn new(
+        rows: impl Iterator<Item = ElementRef<'a>>,
+        locale: Option<Locale>,
+    ) -> Result<Self, ParseError> {
+        let rows: Vec<_> = rows
+            .map(|row| {
+                let key = split_label(row.inner_html().trim())
+                    .and_then(|(label, _)| field_key(locale, label));
+                (key, row)
+            })
+            .collect();
+        if rows.is_empty() {
+            return Err(ParseError("details"));
         }
+        Ok(Fields { rows })
+    }
+
+    fn get(&self, key: &str) -> Option<&ElementRef<'a>> {
+        self.rows
+            .iter()
+            .find(|(k, _)| *k == Some(key))
+            .map(|(_, row)| row)
+    }
+
+    /// the text of the row without its label
+    fn text(&self, key: &str) -> Option<String> {
+        let text = plain(self.get(key)?);
+        let (_, value) = split_label(&text)?;
+        empty2none(value.trim().to_owned())
+    }
+
+    /// the `span`s of the row, eg. the authors
+    fn spans(&self, key: &str) -> Vec<String> {
+        self.get(key)
+            .map(|row| row.select(&SPAN).map(|ele| plain(&ele)).collect())
+            .unwrap_or_default()
+    }
+
+    /// the `span`s of the row as people, eg. the authors
+    fn people(&self, key: &str) -> Vec<Person> {
+        self.get(key)
+            .map(|row| {
+                row.select(&SPAN)
+                    .map(|ele| Person::from_ele(&ele))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// the text of the first link of the row
+    fn link_text(&self, key: &str) -> Option<String> {
+        let row = self.get(key)?;
+        empty2none(plain(&row.select(&A).next()?))
+    }
+
+    /// the rows whose key is not in `known`, or unknown, one per line
+    fn others(&self, known: &[&str]) -> Option<String> {
+        let others: Vec<String> = self
+            .rows
+            .iter()
+            .filter(|(k, _)| k.is_none_or(|k| !known.contains(&k)))
+            .map(|(_, row)| plain(row))
+            .filter(|s| !s.is_empty())
+            .collect();
+        empty2none(others.join("\n"))
+    }
+}
+
+/// split `出版社：上海译文出版社` or `Publisher: ...` into the label and the value, if the
+/// label is plain text, not in a tag
+fn split_label(html: &str) -> Option<(&str, &str)> {
+    let i = html.find(['：', ':'])?;
+    let label = html[..i].trim();
+    let colon_len = html[i..].chars().next()?.len_utf8();
+    if label.is_empty() || label.contains('<') || label.chars().count() > 30 {
+        None
+    } else {
+        Some((label, &html[i + colon_len..]))
+    }
+}
+
+/// a required part of the page is missing
+#[derive(Debug)]
+pub struct ParseError(pub &'static str);
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} not found", self.0)
     }
 }
 
@@ -0,0 +1,64 @@
+//! Lookup of stored items by external ids, over the secondary indexes of [Web::INDEXES].
+
+use crate::{
+    download::{Trees, Web},
+    extract::{Album, Book, Movie},
+    isbn::{normalize_barcode, to_isbn13},
+};
+use sled::Db;
+
+/// an item found by [lookup]
+#[derive(Debug)]
+pub struct Found {
+    pub kind: &'static str,
+    pub id: u32,
+    pub title: String,
+}
+
+/// Find the items whose `index` is `key`, by `isbn`, `imdb`, `barcode` or `source` url.
+///
+/// An ISBN-10 or hyphenated ISBN is normalised first, and so is a barcode.
+pub fn lookup(db: &Db, index: &str, key: &str) -> Vec<Found> {
+    let key = key.trim();
+    let key = match index {
+        "isbn" => match to_isbn13(key) {
+            Some(isbn) => isbn,
+            None => return vec![],
+        },
+        "barcode" => normalize_barcode(key),
+        _ => key.to_owned(),
+    };
+
+    [
+        find::<Movie>(db, index, &key),
+        find::<Book>(db, index, &key),
+        find::<Album>(db, index, &key),
+    ]
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+fn find<T: Web>(db: &Db, index: &str, key: &str) -> Vec<Found> {
+    if !T::INDEXES.contains(&index) {
+        return vec![];
+    }
+    Trees::open::<T>(db)
+        .lookup::<T>(index, key)
+        .into_iter()
+        .map(|(id, one)| Found {
+            kind: T::KIND,
+            id,
+            title: one.title().to_owned(),
+        })
+        .collect()
+}
+
+/// Rebuild the indexes of all kinds from the stored items.
+pub fn reindex(db: &Db) -> Vec<(&'static str, usize)> {
+    vec![
+        (Movie::KIND, Trees::open::<Movie>(db).reindex::<Movie>()),
+        (Book::KIND, Trees::open::<Book>(db).reindex::<Book>()),
+        (Album::KIND, Trees::open::<Album>(db).reindex::<Album>()),
+    ]
+}
@@ -0,0 +1,97 @@
+//! ISBN checksum validation and conversion to ISBN-13, and the same for album barcodes.
+
+/// Normalise `s` to a valid ISBN-13 without hyphens, converting ISBN-10.
+///
+/// Hyphens, spaces and a leading `ISBN` are ignored. Returns `None` if the checksum is wrong
+/// or `s` is not an ISBN at all.
+pub fn to_isbn13(s: &str) -> Option<String> {
+    let s = s.trim();
+    let s = s
+        .strip_prefix("ISBN")
+        .or_else(|| s.strip_prefix("isbn"))
+        .unwrap_or(s)
+        .trim_start_matches([':', '：', ' ']);
+    let isbn: String = s
+        .chars()
+        .filter(|c| !matches!(c, '-' | ' ' | '\u{2010}'))
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+
+    match isbn.len() {
+        13 if is_valid_isbn13(&isbn) => Some(isbn),
+        10 if is_valid_isbn10(&isbn) => Some(isbn10_to_13(&isbn)),
+        _ => None,
+    }
+}
+
+/// `isbn` is 13 digits with a valid check digit
+pub fn is_valid_isbn13(isbn: &str) -> bool {
+    isbn.len() == 13
+        && isbn.bytes().all(|b| b.is_ascii_digit())
+        && (isbn.starts_with("978") || isbn.starts_with("979"))
+        && isbn13_check_digit(&isbn[..12]) == isbn.as_bytes()[12] - b'0'
+}
+
+/// `isbn` is 9 digits and a valid check digit, which may be `X`
+pub fn is_valid_isbn10(isbn: &str) -> bool {
+    let bytes = isbn.as_bytes();
+    if bytes.len() != 10 || !bytes[..9].iter().all(|b| b.is_ascii_digit()) {
+        return false;
+    }
+    let check = match bytes[9] {
+        b'X' | b'x' => 10,
+        b @ b'0'..=b'9' => (b - b'0') as u32,
+        _ => return false,
+    };
+    let sum: u32 = bytes[..9]
+        .iter()
+        .enumerate()
+        .map(|(i, b)| (10 - i as u32) * (b - b'0') as u32)
+        .sum::<u32>()
+        + check;
+    sum.is_multiple_of(11)
+}
+
+/// `isbn` must be a valid ISBN-10
+fn isbn10_to_13(isbn: &str) -> String {
+    let mut isbn13 = format!("978{}", &isbn[..9]);
+    let check = isbn13_check_digit(&isbn13);
+    isbn13.push((b'0' + check) as char);
+    isbn13
+}
+
+/// Normalise a barcode to a valid EAN-13 without hyphens or spaces: a UPC-A (12 digits) or
+/// EAN-8 gets leading zeros. Returns `None` if the check digit is wrong or `s` is not a
+/// barcode at all.
+pub fn to_ean13(s: &str) -> Option<String> {
+    let code: String = s
+        .chars()
+        .filter(|c| !matches!(c, '-' | ' ' | '\u{2010}'))
+        .collect();
+    if !matches!(code.len(), 8 | 12 | 13) || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let code = format!("{code:0>13}");
+    (isbn13_check_digit(&code[..12]) == code.as_bytes()[12] - b'0').then_some(code)
+}
+
+/// Key of a barcode in the `barcode` index: [to_ean13] if it is valid, otherwise the code
+/// in uppercase without hyphens and spaces, as for catalog numbers like `SICX-30001`.
+pub fn normalize_barcode(s: &str) -> String {
+    to_ean13(s).unwrap_or_else(|| {
+        s.chars()
+            .filter(|c| !matches!(c, '-' | ' ' | '\u{2010}'))
+            .map(|c| c.to_ascii_uppercase())
+            .collect()
+    })
+}
+
+/// check digit of the first 12 digits of an ISBN-13 or EAN-13
+fn isbn13_check_digit(digits: &str) -> u8 {
+    let sum: u32 = digits
+        .bytes()
+        .enumerate()
+        .map(|(i, b)| (b - b'0') as u32 * if i.is_multiple_of(2) { 1 } else { 3 })
+        .sum();
+    ((10 - sum % 10) % 10) as u8
+}
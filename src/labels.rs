@@ -0,0 +1,193 @@
+//! Labels of the detail fields in each language of the neodb UI, eg. `出版社：` or
+//! `Publisher:`, mapped to the same field key.
+
+use once_cell::sync::Lazy;
+use scraper::{Html, Selector};
+
+static HTML_LANG: Lazy<Selector> = Lazy::new(|| Selector::parse("html[lang]").unwrap());
+
+/// language of the neodb UI
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Locale {
+    ZhHans,
+    ZhHant,
+    En,
+}
+
+/// label -> field key
+const ZH_HANS: &[(&str, &str)] = &[
+    ("ISBN", "isbn"),
+    ("作者", "author"),
+    ("出版社", "publisher"),
+    ("副标题", "subtitle"),
+    ("译者", "translator"),
+    ("原作名", "original_title"),
+    ("语言", "language"),
+    ("出版时间", "pub_time"),
+    ("装帧", "binding"),
+    ("定价", "price"),
+    ("页数", "pages"),
+    ("IMDb", "imdb"),
+    ("导演", "director"),
+    ("编剧", "writer"),
+    ("主演", "actor"),
+    ("类型", "genre"),
+    ("制片国家/地区", "country"),
+    ("片长", "runtime"),
+    ("季数", "season"),
+    ("集数", "episodes"),
+    ("单集长度", "episode_runtime"),
+    ("上映日期", "release_date"),
+    ("又名", "alias"),
+    ("艺术家", "artist"),
+    ("发行方", "company"),
+    ("发行日期", "release_date"),
+    ("时长", "duration"),
+    ("流派", "genre"),
+    ("介质", "medium"),
+    ("条形码", "barcode"),
+    ("专辑类型", "album_type"),
+];
+
+const ZH_HANT: &[(&str, &str)] = &[
+    ("ISBN", "isbn"),
+    ("作者", "author"),
+    ("出版社", "publisher"),
+    ("副標題", "subtitle"),
+    ("譯者", "translator"),
+    ("原作名", "original_title"),
+    ("語言", "language"),
+    ("出版時間", "pub_time"),
+    ("裝幀", "binding"),
+    ("定價", "price"),
+    ("頁數", "pages"),
+    ("IMDb", "imdb"),
+    ("導演", "director"),
+    ("編劇", "writer"),
+    ("主演", "actor"),
+    ("類型", "genre"),
+    ("製片國家/地區", "country"),
+    ("片長", "runtime"),
+    ("季數", "season"),
+    ("集數", "episodes"),
+    ("單集長度", "episode_runtime"),
+    ("上映日期", "release_date"),
+    ("又名", "alias"),
+    ("藝術家", "artist"),
+    ("發行方", "company"),
+    ("發行日期", "release_date"),
+    ("時長", "duration"),
+    ("流派", "genre"),
+    ("介質", "medium"),
+    ("條形碼", "barcode"),
+    ("專輯類型", "album_type"),
+];
+
+/// compared case-insensitively
+const EN: &[(&str, &str)] = &[
+    ("isbn", "isbn"),
+    ("author", "author"),
+    ("authors", "author"),
+    ("publisher", "publisher"),
+    ("publishing house", "publisher"),
+    ("subtitle", "subtitle"),
+    ("translator", "translator"),
+    ("translators", "translator"),
+    ("original title", "original_title"),
+    ("language", "language"),
+    ("languages", "language"),
+    ("publication date", "pub_time"),
+    ("pub date", "pub_time"),
+    ("binding", "binding"),
+    ("price", "price"),
+    ("pages", "pages"),
+    ("imdb", "imdb"),
+    ("director", "director"),
+    ("directors", "director"),
+    ("playwright", "writer"),
+    ("screenwriter", "writer"),
+    ("writer", "writer"),
+    ("actor", "actor"),
+    ("actors", "actor"),
+    ("cast", "actor"),
+    ("genre", "genre"),
+    ("genres", "genre"),
+    ("country", "country"),
+    ("country/region", "country"),
+    ("countries", "country"),
+    ("length", "runtime"),
+    ("runtime", "runtime"),
+    ("season", "season"),
+    ("number of seasons", "season"),
+    ("episodes", "episodes"),
+    ("number of episodes", "episodes"),
+    ("episode length", "episode_runtime"),
+    ("release date", "release_date"),
+    ("release dates", "release_date"),
+    ("other title", "alias"),
+    ("other titles", "alias"),
+    ("also known as", "alias"),
+    ("artist", "artist"),
+    ("artists", "artist"),
+    ("company", "company"),
+    ("label", "company"),
+    ("duration", "duration"),
+    ("medium", "medium"),
+    ("barcode", "barcode"),
+    ("album type", "album_type"),
+];
+
+impl Locale {
+    const ALL: [Locale; 3] = [Locale::ZhHans, Locale::ZhHant, Locale::En];
+
+    /// From the `lang` of the page, eg. `zh-hans`, `zh-TW`, `en-us`. `None` if missing or
+    /// unknown.
+    pub fn detect(html: &Html) -> Option<Locale> {
+        let lang = html
+            .select(&HTML_LANG)
+            .next()?
+            .value()
+            .attr("lang")?
+            .to_lowercase();
+        if lang.starts_with("en") {
+            Some(Locale::En)
+        } else if lang.starts_with("zh-hant")
+            || lang.starts_with("zh-tw")
+            || lang.starts_with("zh-hk")
+        {
+            Some(Locale::ZhHant)
+        } else if lang.starts_with("zh") {
+            Some(Locale::ZhHans)
+        } else {
+            None
+        }
+    }
+
+    fn labels(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Locale::ZhHans => ZH_HANS,
+            Locale::ZhHant => ZH_HANT,
+            Locale::En => EN,
+        }
+    }
+
+    /// the field key of `label` in this locale
+    pub fn key(self, label: &str) -> Option<&'static str> {
+        let label = label.trim();
+        self.labels()
+            .iter()
+            .find(|(l, _)| match self {
+                Locale::En => l.eq_ignore_ascii_case(label),
+                _ => *l == label,
+            })
+            .map(|(_, key)| *key)
+    }
+}
+
+/// The field key of `label` in `locale`, or in any locale if it is unknown or has no such
+/// label, as pages are not always fully translated.
+pub fn field_key(locale: Option<Locale>, label: &str) -> Option<&'static str> {
+    locale
+        .and_then(|locale| locale.key(label))
+        .or_else(|| Locale::ALL.iter().find_map(|locale| locale.key(label)))
+}
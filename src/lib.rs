@@ -1,3 +1,20 @@
+pub mod api;
 pub mod config;
 pub mod download;
 pub mod extract;
+pub mod index;
+pub mod isbn;
+pub mod labels;
+pub mod metrics;
+pub mod mirror;
+pub mod normalize;
+pub mod people;
+pub mod progress;
+pub mod ratings;
+pub mod runs;
+pub mod schema;
+pub mod search;
+pub mod sources;
+pub mod text;
+pub mod tracks;
+pub mod verify;
@@ -0,0 +1,211 @@
+use crate::download::Outcome;
+use axum::{routing::get, Router};
+use once_cell::sync::Lazy;
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::sync::Semaphore;
+use tracing::{error, info};
+
+pub static METRICS: Lazy<Metrics> = Lazy::new(Metrics::default);
+
+/// upper bounds of the fetch latency buckets, in seconds
+const BUCKETS: [f64; 9] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+/// counters and histograms in Prometheus text format, labelled by `kind`
+/// (`movie`, `book_cover`, `covers`, ...)
+#[derive(Default)]
+pub struct Metrics {
+    /// (kind, status) -> responses, status is `error` for network errors
+    requests: Mutex<BTreeMap<(String, String), u64>>,
+    latency: Mutex<BTreeMap<String, Histogram>>,
+    parse_failures: Mutex<BTreeMap<String, u64>>,
+    bytes: Mutex<BTreeMap<String, u64>>,
+    /// name -> (semaphore, permits)
+    semaphores: Mutex<BTreeMap<String, (Arc<Semaphore>, usize)>>,
+}
+
+impl Metrics {
+    /// Record one request, with the status code or `None` for a network error.
+    pub fn request(&self, kind: &str, status: Option<u16>, elapsed: Duration) {
+        let status = status.map_or_else(|| "error".to_owned(), |s| s.to_string());
+        *self
+            .requests
+            .lock()
+            .unwrap()
+            .entry((kind.to_owned(), status))
+            .or_default() += 1;
+
+        let secs = elapsed.as_secs_f64();
+        let mut latency = self.latency.lock().unwrap();
+        let h = latency.entry(kind.to_owned()).or_default();
+        for (i, le) in BUCKETS.iter().enumerate() {
+            if secs <= *le {
+                h.buckets[i] += 1;
+            }
+        }
+        h.sum += secs;
+        h.count += 1;
+    }
+
+    pub fn outcome(&self, kind: &str, outcome: &Outcome) {
+        match outcome {
+            Outcome::Stored(bytes) => {
+                *self
+                    .bytes
+                    .lock()
+                    .unwrap()
+                    .entry(kind.to_owned())
+                    .or_default() += bytes;
+            }
+            Outcome::ParseError => {
+                *self
+                    .parse_failures
+                    .lock()
+                    .unwrap()
+                    .entry(kind.to_owned())
+                    .or_default() += 1;
+            }
+            _ => {}
+        }
+    }
+
+    /// Expose the permits in use of `semaphore`, which has `permits` in total.
+    pub fn watch_semaphore(&self, name: &str, semaphore: &Arc<Semaphore>, permits: usize) {
+        self.semaphores
+            .lock()
+            .unwrap()
+            .insert(name.to_owned(), (semaphore.clone(), permits));
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "datura_requests_total",
+            "counter",
+            "HTTP responses by status.",
+        );
+        for ((kind, status), n) in self.requests.lock().unwrap().iter() {
+            writeln!(
+                out,
+                "datura_requests_total{{kind=\"{kind}\",status=\"{status}\"}} {n}"
+            )
+            .unwrap();
+        }
+
+        header(
+            &mut out,
+            "datura_fetch_duration_seconds",
+            "histogram",
+            "Time to the response headers.",
+        );
+        for (kind, h) in self.latency.lock().unwrap().iter() {
+            for (le, n) in BUCKETS.iter().zip(h.buckets) {
+                writeln!(
+                    out,
+                    "datura_fetch_duration_seconds_bucket{{kind=\"{kind}\",le=\"{le}\"}} {n}"
+                )
+                .unwrap();
+            }
+            writeln!(
+                out,
+                "datura_fetch_duration_seconds_bucket{{kind=\"{kind}\",le=\"+Inf\"}} {}",
+                h.count
+            )
+            .unwrap();
+            writeln!(
+                out,
+                "datura_fetch_duration_seconds_sum{{kind=\"{kind}\"}} {}",
+                h.sum
+            )
+            .unwrap();
+            writeln!(
+                out,
+                "datura_fetch_duration_seconds_count{{kind=\"{kind}\"}} {}",
+                h.count
+            )
+            .unwrap();
+        }
+
+        header(
+            &mut out,
+            "datura_parse_failures_total",
+            "counter",
+            "Pages which could not be extracted.",
+        );
+        for (kind, n) in self.parse_failures.lock().unwrap().iter() {
+            writeln!(out, "datura_parse_failures_total{{kind=\"{kind}\"}} {n}").unwrap();
+        }
+
+        header(
+            &mut out,
+            "datura_downloaded_bytes_total",
+            "counter",
+            "Bytes of stored pages and files.",
+        );
+        for (kind, n) in self.bytes.lock().unwrap().iter() {
+            writeln!(out, "datura_downloaded_bytes_total{{kind=\"{kind}\"}} {n}").unwrap();
+        }
+
+        let semaphores = self.semaphores.lock().unwrap();
+        header(
+            &mut out,
+            "datura_semaphore_in_use",
+            "gauge",
+            "Concurrent downloads in progress.",
+        );
+        for (name, (semaphore, permits)) in semaphores.iter() {
+            let in_use = permits.saturating_sub(semaphore.available_permits());
+            writeln!(out, "datura_semaphore_in_use{{pool=\"{name}\"}} {in_use}").unwrap();
+        }
+
+        header(
+            &mut out,
+            "datura_semaphore_permits",
+            "gauge",
+            "Max concurrent downloads.",
+        );
+        for (name, (_, permits)) in semaphores.iter() {
+            writeln!(out, "datura_semaphore_permits{{pool=\"{name}\"}} {permits}").unwrap();
+        }
+
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(out, "# HELP {name} {help}").unwrap();
+    writeln!(out, "# TYPE {name} {kind}").unwrap();
+}
+
+/// Serve `GET /metrics` on `addr` in the background, returning the bound address. The
+/// callers log the error and go on without metrics.
+pub fn serve(addr: &str) -> Result<SocketAddr, String> {
+    let addr: SocketAddr = addr
+        .parse()
+        .map_err(|e| format!("metrics address {addr}: {e}"))?;
+    let server = axum::Server::try_bind(&addr).map_err(|e| format!("metrics on {addr}: {e}"))?;
+    let app = Router::new().route("/metrics", get(|| async { METRICS.render() }));
+    let server = server.serve(app.into_make_service());
+    let addr = server.local_addr();
+    info!("metrics on http://{addr}/metrics");
+    tokio::spawn(async move {
+        if let Err(e) = server.await {
+            error!("metrics: {e}");
+        }
+    });
+    Ok(addr)
+}
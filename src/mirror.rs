@@ -0,0 +1,272 @@
+use bincode::{config::standard, Decode, Encode};
+use md5::Md5;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use sled::Tree;
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    path::Path,
+};
+use tracing::error;
+
+/// retryable failures are given up after this many attempts
+pub const MAX_ATTEMPTS: u32 = 5;
+
+/// 404, 410 and other client errors will not change on retry, except timeouts and rate limiting
+pub fn is_permanent(status: u16) -> bool {
+    (400..500).contains(&status) && status != 408 && status != 429
+}
+
+/// one file to mirror
+#[derive(Debug, Deserialize)]
+pub struct Entry {
+    pub url: String,
+    /// file name in the output dir, the last url segment if not provided
+    #[serde(default)]
+    pub dest_name: Option<String>,
+    #[serde(default)]
+    pub sha256: Option<String>,
+    #[serde(default)]
+    pub md5: Option<String>,
+    #[serde(default)]
+    pub size: Option<u64>,
+}
+
+impl Entry {
+    fn from_url(url: String) -> Self {
+        Entry {
+            url,
+            dest_name: None,
+            sha256: None,
+            md5: None,
+            size: None,
+        }
+    }
+
+    pub fn dest_name(&self) -> Option<&str> {
+        match self.dest_name {
+            Some(ref name) if !name.is_empty() => Some(name),
+            _ => self
+                .url
+                .rsplit_once('/')
+                .map(|(_, name)| name)
+                .filter(|name| !name.is_empty()),
+        }
+    }
+
+    /// Check the downloaded file against the expected size and checksums.
+    pub fn verify(&self, fpath: &Path) -> Result<(), String> {
+        if let Some(size) = self.size {
+            let len = fpath.metadata().map_err(|e| e.to_string())?.len();
+            if len != size {
+                return Err(format!("size {len}, expected {size}"));
+            }
+        }
+
+        if self.sha256.is_none() && self.md5.is_none() {
+            return Ok(());
+        }
+
+        let mut sha256 = Sha256::new();
+        let mut md5 = Md5::new();
+        let mut file = File::open(fpath).map_err(|e| e.to_string())?;
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            let n = file.read(&mut buf).map_err(|e| e.to_string())?;
+            if n == 0 {
+                break;
+            }
+            if self.sha256.is_some() {
+                sha256.update(&buf[..n]);
+            }
+            if self.md5.is_some() {
+                md5.update(&buf[..n]);
+            }
+        }
+
+        if let Some(ref expected) = self.sha256 {
+            let actual = format!("{:x}", sha256.finalize());
+            if !actual.eq_ignore_ascii_case(expected) {
+                return Err(format!("sha256 {actual}, expected {expected}"));
+            }
+        }
+        if let Some(ref expected) = self.md5 {
+            let actual = format!("{:x}", md5.finalize());
+            if !actual.eq_ignore_ascii_case(expected) {
+                return Err(format!("md5 {actual}, expected {expected}"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Read a manifest, by file extension:
+///
+/// * `.csv`: with a header row, columns `url`, `dest_name`, `sha256`, `md5`, `size`
+/// * `.jsonl` / `.ndjson`: one object per line with the same keys
+/// * others: one url per line
+///
+/// The error tells the line of the first malformed row.
+pub fn read_manifest(fpath: &str) -> Result<Vec<Entry>, String> {
+    let file = File::open(fpath).map_err(|e| format!("{fpath}: {e}"))?;
+    if fpath.ends_with(".csv") {
+        return csv::Reader::from_reader(file)
+            .deserialize()
+            .map(|r| r.map_err(|e| format!("{fpath}: {e}")))
+            .collect();
+    }
+
+    let json = fpath.ends_with(".jsonl") || fpath.ends_with(".ndjson");
+    let mut entries = vec![];
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let err = |e: &dyn std::fmt::Display| format!("{fpath}:{}: {e}", i + 1);
+        let line = line.map_err(|e| err(&e))?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if json {
+            entries.push(serde_json::from_str(line).map_err(|e| err(&e))?);
+        } else {
+            entries.push(Entry::from_url(line.to_owned()));
+        }
+    }
+    Ok(entries)
+}
+
+/// Key the entries by file name.
+///
+/// Repeated entries of the same url are merged. A file name claimed by different urls is
+/// a collision: it is logged and none of its entries is kept, rather than letting one
+/// download overwrite another. So are names which are not a plain file name.
+pub fn by_dest_name(entries: Vec<Entry>) -> HashMap<String, Entry> {
+    let mut names: HashMap<String, Vec<Entry>> = HashMap::new();
+    for entry in entries {
+        match entry.dest_name() {
+            Some(name) if is_plain_name(name) => {
+                let same = names.entry(name.to_owned()).or_default();
+                if !same.iter().any(|e| e.url == entry.url) {
+                    same.push(entry);
+                }
+            }
+            _ => error!("invalid file name for {}", entry.url),
+        }
+    }
+
+    let mut out = HashMap::with_capacity(names.len());
+    for (name, mut same) in names {
+        if same.len() == 1 {
+            out.insert(name, same.pop().unwrap());
+        } else {
+            let urls: Vec<&str> = same.iter().map(|e| e.url.as_str()).collect();
+            error!("file name collision: {name} <- {urls:?}");
+        }
+    }
+    out
+}
+
+/// Drop the entries whose file name an earlier run downloaded from another url, as recorded
+/// in `urls`, and record the url of the others. A name not downloaded yet, eg. for a
+/// mistyped url, is given to the new url, with its download `state` reset.
+pub fn claim_names(entries: &mut HashMap<String, Entry>, urls: &Tree, state: &Tree) {
+    entries.retain(|name, entry| match urls.get(name).unwrap() {
+        Some(url) if url == entry.url.as_bytes() => true,
+        Some(url) if matches!(State::get(state, name), Some(State::Done)) => {
+            error!(
+                "file name collision: {name} <- {}, taken by {} in an earlier run",
+                entry.url,
+                String::from_utf8_lossy(&url)
+            );
+            false
+        }
+        Some(_) => {
+            state.remove(name.as_str()).unwrap();
+            urls.insert(name.as_str(), entry.url.as_str()).unwrap();
+            true
+        }
+        None => {
+            urls.insert(name.as_str(), entry.url.as_str()).unwrap();
+            true
+        }
+    });
+}
+
+fn is_plain_name(name: &str) -> bool {
+    name != "." && name != ".." && !name.contains(['/', '\\'])
+}
+
+/// download state of one file, stored in the `state` tree keyed by file name; the `urls`
+/// tree keeps the url of each name, see [claim_names]
+#[derive(Debug, Encode, Decode)]
+pub enum State {
+    Done,
+    /// permanent failure with http status code, never retried
+    Failed(u16),
+    /// body exceeds this max file size, retried once the max size is larger
+    TooLarge(u64),
+    /// network error, server error or checksum mismatch, retried until [MAX_ATTEMPTS]
+    Retry(u32),
+}
+
+impl State {
+    /// The state of `fname`, `None` if it was never tried or its state cannot be decoded,
+    /// eg. written by another version, so it is downloaded again.
+    pub fn get(tree: &Tree, fname: &str) -> Option<State> {
+        let v = tree.get(fname).unwrap()?;
+        match bincode::decode_from_slice(&v, standard()) {
+            Ok((state, _)) => Some(state),
+            Err(e) => {
+                error!("state of {fname}: {e}, downloading it again");
+                None
+            }
+        }
+    }
+
+    pub fn set(self, tree: &Tree, fname: &str) {
+        let encoded = bincode::encode_to_vec(self, standard()).unwrap();
+        tree.insert(fname, encoded).unwrap();
+    }
+
+    /// whether the file should be downloaded in this run, with files up to `max_size` bytes
+    pub fn pending(&self, max_size: u64) -> bool {
+        match self {
+            State::Done | State::Failed(_) => false,
+            State::TooLarge(limit) => max_size > *limit,
+            State::Retry(n) => *n < MAX_ATTEMPTS,
+        }
+    }
+
+    pub fn retry(tree: &Tree, fname: &str) {
+        let n = match State::get(tree, fname) {
+            Some(State::Retry(n)) => n,
+            _ => 0,
+        };
+        State::Retry(n + 1).set(tree, fname);
+    }
+
+    /// Record a response with the error `status`, failed for good if [is_permanent].
+    pub fn failed(tree: &Tree, fname: &str, status: u16) {
+        if is_permanent(status) {
+            State::Failed(status).set(tree, fname);
+        } else {
+            State::retry(tree, fname);
+        }
+    }
+
+    /// Whether `fname` should be downloaded in this run, `exists` telling if the file is in
+    /// the output dir. A file from before the state db is recorded as done, and a done
+    /// file deleted since is downloaded again, as is a file too large for a smaller
+    /// `max_size` than this one.
+    pub fn wanted(tree: &Tree, fname: &str, exists: bool, max_size: u64) -> bool {
+        match State::get(tree, fname) {
+            Some(State::Done) => !exists,
+            None if exists => {
+                State::Done.set(tree, fname);
+                false
+            }
+            state => state.is_none_or(|s| s.pending(max_size)),
+        }
+    }
+}
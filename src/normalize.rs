@@ -0,0 +1,283 @@
+//! Typed values parsed from the raw strings of the pages, eg. `39.00元`, `2021-5`, `120分钟`.
+
+use bincode::{Decode, Encode};
+use serde::Serialize;
+
+/// amount of money
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Serialize)]
+pub struct Price {
+    /// in hundredths of the unit, eg. `3900` for `39.00元`
+    pub cents: u64,
+    /// ISO 4217 code, if recognized
+    pub currency: Option<String>,
+}
+
+/// currency markers, longer ones first so `NT$` wins over `$`
+const CURRENCIES: [(&str, &str); 23] = [
+    ("新台币", "TWD"),
+    ("新臺幣", "TWD"),
+    ("港币", "HKD"),
+    ("港幣", "HKD"),
+    ("港元", "HKD"),
+    ("人民币", "CNY"),
+    ("日元", "JPY"),
+    ("美元", "USD"),
+    ("NT$", "TWD"),
+    ("NTD", "TWD"),
+    ("TWD", "TWD"),
+    ("HK$", "HKD"),
+    ("HKD", "HKD"),
+    ("CNY", "CNY"),
+    ("RMB", "CNY"),
+    ("JPY", "JPY"),
+    ("USD", "USD"),
+    ("EUR", "EUR"),
+    ("GBP", "GBP"),
+    ("US$", "USD"),
+    ("元", "CNY"),
+    ("円", "JPY"),
+    ("$", "USD"),
+];
+
+/// `39.00元`, `CNY 39.00`, `NT$320`, `NT$1,200`, `1,200円`, `12,50 €`, `$12.99`, `¥1200`
+/// (taken as CNY)
+pub fn parse_price(s: &str) -> Option<Price> {
+    let plain = with_decimal_points(s);
+    let (number, _) = first_number(&plain)?;
+    let cents = (number.parse::<f64>().ok()? * 100.0).round() as u64;
+
+    let upper = s.to_uppercase();
+    let currency = CURRENCIES
+        .iter()
+        .find(|(marker, _)| upper.contains(marker))
+        .map(|(_, code)| *code)
+        .or_else(|| {
+            if s.contains('€') {
+                Some("EUR")
+            } else if s.contains('£') {
+                Some("GBP")
+            } else if s.contains('¥') || s.contains('￥') {
+                Some("CNY")
+            } else {
+                None
+            }
+        })
+        .map(|code| code.to_owned());
+
+    Some(Price { cents, currency })
+}
+
+/// `320`, `320页`, `320 pages`
+pub fn parse_pages(s: &str) -> Option<u32> {
+    let (number, _) = first_number(s)?;
+    number.parse().ok().filter(|n| *n > 0)
+}
+
+/// how much of a [PartialDate] is known
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Precision {
+    Year,
+    Month,
+    Day,
+}
+
+/// a date of which the month and the day may be unknown. Ordered chronologically,
+/// an unknown month or day before the known ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, Serialize)]
+pub struct PartialDate {
+    pub year: u16,
+    pub month: Option<u8>,
+    pub day: Option<u8>,
+}
+
+impl PartialDate {
+    pub fn precision(&self) -> Precision {
+        match (self.month, self.day) {
+            (Some(_), Some(_)) => Precision::Day,
+            (Some(_), None) => Precision::Month,
+            _ => Precision::Year,
+        }
+    }
+}
+
+/// `2021`, `2021-5`, `2021-05-01`, `2021/5/1`, `2021.5`, `2021年5月1日`, `2010-10-01(中国大陆)`
+pub fn parse_date(s: &str) -> Option<PartialDate> {
+    let mut parts = s
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|p| !p.is_empty());
+
+    let year = parts.next().filter(|y| y.len() == 4)?.parse().ok()?;
+    let month = parts
+        .next()
+        .and_then(|m| m.parse().ok())
+        .filter(|m| (1..=12).contains(m));
+    let day = month.and_then(|_| {
+        parts
+            .next()
+            .and_then(|d| d.parse().ok())
+            .filter(|d| (1..=31).contains(d))
+    });
+
+    Some(PartialDate { year, month, day })
+}
+
+/// minutes of `120分钟`, `120 分钟(中国大陆)`, `2小时`, `1小时30分钟`, `90 min`, `1h 30m`,
+/// `45分钟×12集` (of one episode)
+pub fn parse_minutes(s: &str) -> Option<u32> {
+    // `120分钟(中国大陆) / 130分钟(美国)`: only the first version counts
+    let mut rest = s.split(['/', '(', '（']).next().unwrap_or_default();
+    let mut minutes = 0;
+    // hours may be followed by minutes, the first number of minutes ends the duration
+    while let Some((number, after)) = first_number(rest) {
+        let value: f64 = number.parse().ok()?;
+        let unit = after.trim_start();
+        if unit.starts_with("小时") || unit.starts_with(['h', 'H']) {
+            minutes += (value * 60.0).round() as u32;
+        } else {
+            minutes += value.round() as u32;
+            break;
+        }
+        rest = after;
+    }
+
+    Some(minutes).filter(|m| *m > 0)
+}
+
+/// `8.8`, ` 9.5 /10`, `None` for `暂无评分` or out of 0 to 10
+pub fn parse_score(s: &str) -> Option<f64> {
+    let (number, _) = first_number(s)?;
+    number.parse().ok().filter(|n| (0.0..=10.0).contains(n))
+}
+
+/// `(1432人评分)`, `98 个评分`, `1,234 ratings`, `1.2万人评分`
+pub fn parse_count(s: &str) -> Option<u32> {
+    let s = s.replace(',', "");
+    let (number, after) = first_number(&s)?;
+    let value: f64 = number.parse().ok()?;
+    let value = if after.trim_start().starts_with(['万', '萬']) {
+        value * 10_000.0
+    } else {
+        value
+    };
+    Some(value.round() as u32)
+}
+
+/// seconds of `4:23`, `1:02:03`, `(3:21)`
+pub fn parse_duration(s: &str) -> Option<u32> {
+    let s = s.trim().trim_matches(['(', ')', '[', ']']).trim();
+    let parts: Vec<u32> = s
+        .split(':')
+        .map(|p| p.trim().parse().ok())
+        .collect::<Option<_>>()?;
+    match parts.as_slice() {
+        [m, sec] if *sec < 60 => Some(m * 60 + sec),
+        [h, m, sec] if *m < 60 && *sec < 60 => Some(h * 3600 + m * 60 + sec),
+        _ => None,
+    }
+    .filter(|secs| *secs > 0)
+}
+
+/// genre names -> canonical name, compared in lowercase
+const GENRES: [(&str, &str); 48] = [
+    ("摇滚", "Rock"),
+    ("搖滾", "Rock"),
+    ("rock", "Rock"),
+    ("流行", "Pop"),
+    ("pop", "Pop"),
+    ("电子", "Electronic"),
+    ("電子", "Electronic"),
+    ("electronic", "Electronic"),
+    ("electronica", "Electronic"),
+    ("民谣", "Folk"),
+    ("民謠", "Folk"),
+    ("folk", "Folk"),
+    ("爵士", "Jazz"),
+    ("jazz", "Jazz"),
+    ("古典", "Classical"),
+    ("classical", "Classical"),
+    ("说唱", "Hip-Hop"),
+    ("說唱", "Hip-Hop"),
+    ("嘻哈", "Hip-Hop"),
+    ("hip-hop", "Hip-Hop"),
+    ("hip hop", "Hip-Hop"),
+    ("rap", "Hip-Hop"),
+    ("原声", "Soundtrack"),
+    ("原聲", "Soundtrack"),
+    ("soundtrack", "Soundtrack"),
+    ("轻音乐", "Easy Listening"),
+    ("輕音樂", "Easy Listening"),
+    ("easy listening", "Easy Listening"),
+    ("世界音乐", "World"),
+    ("世界音樂", "World"),
+    ("world", "World"),
+    ("布鲁斯", "Blues"),
+    ("藍調", "Blues"),
+    ("blues", "Blues"),
+    ("乡村", "Country"),
+    ("鄉村", "Country"),
+    ("country", "Country"),
+    ("金属", "Metal"),
+    ("金屬", "Metal"),
+    ("metal", "Metal"),
+    ("朋克", "Punk"),
+    ("punk", "Punk"),
+    ("放克/灵魂/r&b", "Funk / Soul"),
+    ("funk / soul", "Funk / Soul"),
+    ("r&b", "R&B"),
+    ("雷鬼", "Reggae"),
+    ("reggae", "Reggae"),
+    ("拉丁", "Latin"),
+];
+
+/// `摇滚`, `Rock / Pop`, `rock, 电子` to canonical genre names, unknown ones kept as is
+pub fn parse_genres(s: &str) -> Vec<String> {
+    let whole = s.trim().to_lowercase();
+    if let Some((_, genre)) = GENRES.iter().find(|(name, _)| *name == whole) {
+        return vec![genre.to_string()];
+    }
+    let mut genres: Vec<String> = vec![];
+    for part in s.split(['/', ',', ';', '、', '，', '；', '|']) {
+        let part = part.trim();
+        if part.is_empty() {
+            continue;
+        }
+        let lower = part.to_lowercase();
+        let genre = GENRES
+            .iter()
+            .find(|(name, _)| *name == lower)
+            .map_or(part, |(_, genre)| genre);
+        if !genres.iter().any(|g| g == genre) {
+            genres.push(genre.to_owned());
+        }
+    }
+    genres
+}
+
+/// `s` without thousands separators, a comma followed by 3 digits, and with decimal commas
+/// as points: `1,200.50` -> `1200.50`, `12,50` -> `12.50`
+fn with_decimal_points(s: &str) -> String {
+    let chars: Vec<char> = s.chars().collect();
+    let mut out = String::with_capacity(s.len());
+    for (i, c) in chars.iter().enumerate() {
+        let digit_at = |j: usize| chars.get(j).is_some_and(|c| c.is_ascii_digit());
+        if *c == ',' && i > 0 && digit_at(i - 1) && digit_at(i + 1) {
+            if (1..=3).all(|n| digit_at(i + n)) && !digit_at(i + 4) {
+                continue;
+            }
+            out.push('.');
+        } else {
+            out.push(*c);
+        }
+    }
+    out
+}
+
+/// the first decimal number in `s`, and the rest of `s` after it
+fn first_number(s: &str) -> Option<(&str, &str)> {
+    let start = s.find(|c: char| c.is_ascii_digit())?;
+    let len = s[start..]
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len() - start);
+    let number = s[start..start + len].trim_end_matches('.');
+    Some((number, &s[start + len..]))
+}
@@ -0,0 +1,121 @@
+//! The `people` tree, from each person credited on an item to the items they appear in.
+//!
+//! Keys are `name 0x00 url 0x00 kind 0x00 id`, with an empty url for a person without
+//! link, so namesakes with different pages are told apart and all the people of a name
+//! are found by prefix. Values are the roles of the person in the item, eg.
+//! `director,writer`.
+
+use crate::{download::title, extract::Person};
+use sled::{Db, Tree};
+use std::collections::BTreeMap;
+
+/// an item a person appears in, found by [appearances]
+#[derive(Debug)]
+pub struct Appearance {
+    pub name: String,
+    pub url: Option<String>,
+    pub kind: String,
+    pub id: u32,
+    pub roles: Vec<String>,
+    pub title: Option<String>,
+}
+
+fn item_key(person_prefix: Vec<u8>, kind: &str, id: u32) -> Vec<u8> {
+    let mut key = person_prefix;
+    key.extend(kind.as_bytes());
+    key.push(0);
+    key.extend(id.to_be_bytes());
+    key
+}
+
+fn person_prefix(name: &str, url: Option<&str>) -> Vec<u8> {
+    let mut prefix = name.as_bytes().to_vec();
+    prefix.push(0);
+    prefix.extend(url.unwrap_or_default().as_bytes());
+    prefix.push(0);
+    prefix
+}
+
+/// each person of `people`, by key prefix, with their roles in credit order
+fn roles(people: &[(&Person, &'static str)]) -> BTreeMap<Vec<u8>, Vec<&'static str>> {
+    let mut roles: BTreeMap<Vec<u8>, Vec<&str>> = BTreeMap::new();
+    for (person, role) in people {
+        if person.name.is_empty() {
+            continue;
+        }
+        let entry = roles.entry(person_prefix(&person.name, person.url.as_deref()));
+        let item_roles = entry.or_default();
+        if !item_roles.contains(role) {
+            item_roles.push(role);
+        }
+    }
+    roles
+}
+
+/// Point the [Web::people] of the item `kind` `id` to it.
+pub fn index_people(tree: &Tree, kind: &str, id: u32, people: &[(&Person, &'static str)]) {
+    for (prefix, roles) in roles(people) {
+        tree.insert(item_key(prefix, kind, id), roles.join(",").as_bytes())
+            .unwrap();
+    }
+}
+
+/// Drop the entries of `people` for the item `kind` `id`, eg. its former version.
+pub fn unindex_people(tree: &Tree, kind: &str, id: u32, people: &[(&Person, &'static str)]) {
+    for prefix in roles(people).into_keys() {
+        tree.remove(item_key(prefix, kind, id)).unwrap();
+    }
+}
+
+/// Drop the entries of every item of `kind`, before rebuilding them.
+pub fn clear_kind(tree: &Tree, kind: &str) {
+    for k in tree.iter().keys() {
+        let k = k.unwrap();
+        if split_key(&k).is_some_and(|(_, _, k, _)| k == kind) {
+            tree.remove(k).unwrap();
+        }
+    }
+}
+
+/// name, url, kind and id of a key
+fn split_key(key: &[u8]) -> Option<(&str, &str, &str, u32)> {
+    let (rest, id) = key.split_at_checked(key.len().checked_sub(4)?)?;
+    let mut parts = rest.splitn(3, |b| *b == 0);
+    let name = std::str::from_utf8(parts.next()?).ok()?;
+    let url = std::str::from_utf8(parts.next()?).ok()?;
+    let kind = std::str::from_utf8(parts.next()?.strip_suffix(&[0])?).ok()?;
+    Some((name, url, kind, u32::from_be_bytes(id.try_into().ok()?)))
+}
+
+/// The items the people named `name` appear in, grouped by person page. With `url`, only
+/// the person of that page.
+pub fn appearances(db: &Db, name: &str, url: Option<&str>) -> Vec<Appearance> {
+    let tree = db.open_tree("people").unwrap();
+    let mut prefix = name.trim().as_bytes().to_vec();
+    prefix.push(0);
+    if let Some(url) = url {
+        prefix.extend(url.as_bytes());
+        prefix.push(0);
+    }
+
+    let mut found = vec![];
+    for kv in tree.scan_prefix(prefix) {
+        let (k, v) = kv.unwrap();
+        let Some((name, url, kind, id)) = split_key(&k) else {
+            continue;
+        };
+        let title = title(db, kind, id);
+        found.push(Appearance {
+            name: name.to_owned(),
+            url: (!url.is_empty()).then(|| url.to_owned()),
+            kind: kind.to_owned(),
+            id,
+            roles: String::from_utf8_lossy(&v)
+                .split(',')
+                .map(|s| s.to_owned())
+                .collect(),
+            title,
+        });
+    }
+    found
+}
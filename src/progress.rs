@@ -0,0 +1,204 @@
+use crate::download::Outcome;
+use bincode::{Decode, Encode};
+use std::{
+    io::Write,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering::Relaxed},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::task::JoinHandle;
+use tracing::info;
+
+/// snapshot of [Progress]
+#[derive(Debug, Default, Clone, Copy, Encode, Decode)]
+pub struct Counts {
+    pub attempted: u64,
+    pub ok: u64,
+    pub unchanged: u64,
+    pub not_found: u64,
+    pub failed: u64,
+    pub parse_errors: u64,
+    pub bytes: u64,
+}
+
+impl std::iter::Sum for Counts {
+    fn sum<I: Iterator<Item = Counts>>(iter: I) -> Self {
+        iter.fold(Counts::default(), |a, b| Counts {
+            attempted: a.attempted + b.attempted,
+            ok: a.ok + b.ok,
+            unchanged: a.unchanged + b.unchanged,
+            not_found: a.not_found + b.not_found,
+            failed: a.failed + b.failed,
+            parse_errors: a.parse_errors + b.parse_errors,
+            bytes: a.bytes + b.bytes,
+        })
+    }
+}
+
+/// counters of a batch of downloads, reported at a fixed interval
+pub struct Progress {
+    name: String,
+    start: Instant,
+    total: u64,
+    done: AtomicU64,
+    ok: AtomicU64,
+    unchanged: AtomicU64,
+    not_found: AtomicU64,
+    failed: AtomicU64,
+    parse_errors: AtomicU64,
+    bytes: AtomicU64,
+    finished: AtomicBool,
+    bar: bool,
+}
+
+impl Progress {
+    /// With `bar`, a progress bar is also drawn on stderr.
+    pub fn new(name: &str, total: usize, bar: bool) -> Arc<Self> {
+        Arc::new(Progress {
+            name: name.to_owned(),
+            start: Instant::now(),
+            total: total as u64,
+            done: AtomicU64::new(0),
+            ok: AtomicU64::new(0),
+            unchanged: AtomicU64::new(0),
+            not_found: AtomicU64::new(0),
+            failed: AtomicU64::new(0),
+            parse_errors: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+            finished: AtomicBool::new(false),
+            bar,
+        })
+    }
+
+    pub fn record(&self, outcome: &Outcome) {
+        match outcome {
+            Outcome::Stored(bytes) => {
+                self.ok.fetch_add(1, Relaxed);
+                self.bytes.fetch_add(*bytes, Relaxed);
+            }
+            Outcome::Unchanged => {
+                self.unchanged.fetch_add(1, Relaxed);
+            }
+            Outcome::NotFound => {
+                self.not_found.fetch_add(1, Relaxed);
+            }
+            Outcome::Failed => {
+                self.failed.fetch_add(1, Relaxed);
+            }
+            Outcome::ParseError => {
+                self.parse_errors.fetch_add(1, Relaxed);
+            }
+            Outcome::Skipped => {}
+        }
+        self.done.fetch_add(1, Relaxed);
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn counts(&self) -> Counts {
+        Counts {
+            attempted: self.done.load(Relaxed),
+            ok: self.ok.load(Relaxed),
+            unchanged: self.unchanged.load(Relaxed),
+            not_found: self.not_found.load(Relaxed),
+            failed: self.failed.load(Relaxed),
+            parse_errors: self.parse_errors.load(Relaxed),
+            bytes: self.bytes.load(Relaxed),
+        }
+    }
+
+    /// Report every `interval` until [Progress::finish] is called.
+    pub fn spawn_reporter(self: &Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        let progress = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            // the first tick completes immediately
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                if progress.finished.load(Relaxed) {
+                    break;
+                }
+                progress.report();
+            }
+        })
+    }
+
+    /// Stop the reporter and report the final numbers.
+    pub fn finish(&self) {
+        self.finished.store(true, Relaxed);
+        self.report();
+        if self.bar {
+            eprintln!();
+        }
+    }
+
+    fn report(&self) {
+        let done = self.done.load(Relaxed);
+        let remaining = self.total.saturating_sub(done);
+        let secs = self.start.elapsed().as_secs_f64().max(0.001);
+        let bytes = self.bytes.load(Relaxed);
+        let rate = (bytes as f64 / secs) as u64;
+        let eta = if done == 0 {
+            "-".to_owned()
+        } else {
+            format_secs((remaining as f64 * secs / done as f64) as u64)
+        };
+
+        info!(
+            name = %self.name,
+            done,
+            remaining,
+            ok = self.ok.load(Relaxed),
+            unchanged = self.unchanged.load(Relaxed),
+            not_found = self.not_found.load(Relaxed),
+            failed = self.failed.load(Relaxed),
+            parse_errors = self.parse_errors.load(Relaxed),
+            bytes,
+            "{}/s, eta {}",
+            format_bytes(rate),
+            eta
+        );
+
+        if self.bar {
+            const WIDTH: u64 = 30;
+            let filled = (done * WIDTH)
+                .checked_div(self.total)
+                .unwrap_or(WIDTH)
+                .min(WIDTH);
+            let mut stderr = std::io::stderr().lock();
+            write!(
+                stderr,
+                "\r{} [{}{}] {}/{} {}/s eta {}   ",
+                self.name,
+                "#".repeat(filled as usize),
+                ".".repeat((WIDTH - filled) as usize),
+                done,
+                self.total,
+                format_bytes(rate),
+                eta
+            )
+            .ok();
+            stderr.flush().ok();
+        }
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+fn format_secs(secs: u64) -> String {
+    format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
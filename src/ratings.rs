@@ -0,0 +1,61 @@
+//! Rating history of the items, to chart how ratings change.
+//!
+//! For each kind, the `{kind}_ratings` tree holds `id time -> Rating`, the time in seconds
+//! since the epoch, both big endian so the history of an item is in order. A rating is
+//! only recorded when it differs from the last one of the item.
+
+use crate::{download::u32_to_ivec, extract::Rating};
+use bincode::config::standard;
+use sled::Tree;
+
+fn key(id: u32, at: u64) -> Vec<u8> {
+    let mut key = id.to_be_bytes().to_vec();
+    key.extend(at.to_be_bytes());
+    key
+}
+
+fn decode_rating(v: &[u8]) -> Option<Rating> {
+    bincode::decode_from_slice(v, standard())
+        .ok()
+        .map(|(rating, _)| rating)
+}
+
+/// Record `rating` of the item `id` at `at`, unless it is empty or the same as the last
+/// recorded. Returns whether it was recorded.
+pub fn record(tree: &Tree, id: u32, rating: &Rating, at: u64) -> bool {
+    if rating.is_empty() {
+        return false;
+    }
+    let last = tree
+        .scan_prefix(u32_to_ivec(id))
+        .values()
+        .next_back()
+        .and_then(|v| decode_rating(&v.unwrap()));
+    if last.as_ref() == Some(rating) {
+        return false;
+    }
+    let encoded = bincode::encode_to_vec(rating, standard()).unwrap();
+    tree.insert(key(id, at), encoded).unwrap();
+    true
+}
+
+/// The recorded ratings of the item `id`, oldest first, with their time.
+pub fn history(tree: &Tree, id: u32) -> Vec<(u64, Rating)> {
+    tree.scan_prefix(u32_to_ivec(id))
+        .filter_map(|kv| {
+            let (k, v) = kv.unwrap();
+            let at = u64::from_be_bytes(k.get(4..)?.try_into().ok()?);
+            Some((at, decode_rating(&v)?))
+        })
+        .collect()
+}
+
+/// whether `key` is an `id time` key of a ratings tree
+pub fn is_key(key: &[u8]) -> bool {
+    key.len() == 12
+}
+
+/// whether `v` is a value of a ratings tree
+pub fn is_value(v: &[u8]) -> bool {
+    decode_rating(v).is_some()
+}
@@ -0,0 +1,163 @@
+use crate::{
+    progress::Counts,
+    schema::{decode, encode, Versioned},
+};
+use bincode::{Decode, Encode};
+use sled::{Db, IVec};
+use std::{
+    fmt,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::error;
+
+/// summary of one crawl of every kind, stored in the `runs` tree keyed by a generated id
+#[derive(Debug, Encode, Decode)]
+pub struct Run {
+    /// `full`, `incremental` or `refresh`
+    pub mode: String,
+    /// unix seconds
+    pub start: u64,
+    /// unix seconds
+    pub end: u64,
+    /// in crawl order
+    pub kinds: Vec<KindRun>,
+}
+
+/// counters of one kind of item in a [Run]
+#[derive(Debug, Encode, Decode)]
+pub struct KindRun {
+    /// `movie`, `book` or `album`
+    pub kind: String,
+    pub items: Counts,
+    pub covers: Counts,
+}
+
+impl Versioned for Run {
+    /// 1: the first format
+    const VERSION: u8 = 1;
+
+    fn decode_old(_: u8, _: &[u8]) -> Option<Self> {
+        None
+    }
+}
+
+/// the run of `v` stored as `k`, `None` after logging if it cannot be decoded
+fn decode_run(k: &[u8], v: &IVec) -> Option<Run> {
+    let run = decode(v);
+    if run.is_none() {
+        error!("run {k:?}: undecodable, see `verify`");
+    }
+    run
+}
+
+impl Run {
+    pub fn save(&self, db: &Db) -> u64 {
+        let id = db.generate_id().unwrap();
+        db.open_tree("runs")
+            .unwrap()
+            .insert(id.to_be_bytes(), encode(self))
+            .unwrap();
+        id
+    }
+
+    pub fn get(db: &Db, id: u64) -> Option<Run> {
+        let key = id.to_be_bytes();
+        let v = db.open_tree("runs").unwrap().get(key).unwrap()?;
+        decode_run(&key, &v)
+    }
+
+    /// all runs, oldest first, without the undecodable ones
+    pub fn list(db: &Db) -> Vec<(u64, Run)> {
+        db.open_tree("runs")
+            .unwrap()
+            .iter()
+            .filter_map(|kv| {
+                let (k, v) = kv.unwrap();
+                let id = u64::from_be_bytes(k.as_ref().try_into().ok()?);
+                Some((id, decode_run(&k, &v)?))
+            })
+            .collect()
+    }
+
+    /// counters of the items of every kind
+    pub fn items(&self) -> Counts {
+        self.kinds.iter().map(|k| k.items).sum()
+    }
+
+    /// counters of the covers of every kind
+    pub fn covers(&self) -> Counts {
+        self.kinds.iter().map(|k| k.covers).sum()
+    }
+
+    /// one line summary
+    pub fn summary(&self) -> String {
+        let (items, covers) = (self.items(), self.covers());
+        let kinds: Vec<&str> = self.kinds.iter().map(|k| k.kind.as_str()).collect();
+        format!(
+            "{} {:>6}s {:<11} {:<16} attempted {} stored {} 404 {} failed {} parse errors {} covers {} bytes {}",
+            format_time(self.start),
+            self.end.saturating_sub(self.start),
+            self.mode,
+            kinds.join(","),
+            items.attempted,
+            items.ok,
+            items.not_found,
+            items.failed,
+            items.parse_errors,
+            covers.ok,
+            items.bytes + covers.bytes,
+        )
+    }
+}
+
+impl fmt::Display for Run {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "mode:    {}", self.mode)?;
+        writeln!(f, "start:   {}", format_time(self.start))?;
+        writeln!(f, "end:     {}", format_time(self.end))?;
+        for kind in &self.kinds {
+            for (name, c) in [("items", &kind.items), ("covers", &kind.covers)] {
+                writeln!(f, "{} {name}:", kind.kind)?;
+                writeln!(f, "  attempted:    {}", c.attempted)?;
+                writeln!(f, "  stored:       {}", c.ok)?;
+                writeln!(f, "  unchanged:    {}", c.unchanged)?;
+                writeln!(f, "  404:          {}", c.not_found)?;
+                writeln!(f, "  failed:       {}", c.failed)?;
+                writeln!(f, "  parse errors: {}", c.parse_errors)?;
+                writeln!(f, "  bytes:        {}", c.bytes)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// format unix seconds as `YYYY-MM-DD hh:mm:ss` in UTC
+pub fn format_time(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}",
+        rem / 3600,
+        rem / 60 % 60,
+        rem % 60
+    )
+}
@@ -0,0 +1,611 @@
+//! Versioned encoding of the stored items.
+//!
+//! An item is stored as `0xFF version` followed by its bincode encoding. `0xFF` never starts
+//! a bincode varint, so values written before versioning, which start with the length of
+//! the title, are told apart and decoded as version 0. Older versions are decoded with the
+//! struct they were written with and converted to the current one; `migrate` rewrites them.
+
+use crate::{
+    download::{ivec_to_u32, split_index_key, Trees, Web},
+    extract::{Album, Book, Movie, Person, Rating},
+    isbn::to_isbn13,
+    normalize::{
+        parse_date, parse_genres, parse_minutes, parse_pages, parse_price, PartialDate, Price,
+    },
+    sources::ExternalSource,
+    text::{block_str, plain_str, sanitize_str},
+    tracks::{from_page, numbered},
+};
+use bincode::{config::standard, Decode, Encode};
+use sled::Db;
+use tracing::error;
+
+const MARKER: u8 = 0xFF;
+
+pub trait Versioned: Encode + Decode + Sized {
+    /// version written by [encode]
+    const VERSION: u8;
+
+    /// decode `bytes` written by the older `version`
+    fn decode_old(version: u8, bytes: &[u8]) -> Option<Self>;
+}
+
+/// version prefix and bincode encoding of `one`
+pub fn encode<T: Versioned>(one: &T) -> Vec<u8> {
+    let mut encoded = vec![MARKER, T::VERSION];
+    encoded.extend(bincode::encode_to_vec(one, standard()).unwrap());
+    encoded
+}
+
+/// Decode a stored value of any known version. `None` if it is corrupt or from a newer
+/// version.
+pub fn decode<T: Versioned>(v: &[u8]) -> Option<T> {
+    match version(v) {
+        version if version == T::VERSION => decode_bincode(&v[2..]),
+        0 => T::decode_old(0, v),
+        version => T::decode_old(version, &v[2..]),
+    }
+}
+
+/// schema version of a stored value, `0` if it has no prefix
+pub fn version(v: &[u8]) -> u8 {
+    match v {
+        [MARKER, version, ..] => *version,
+        _ => 0,
+    }
+}
+
+fn decode_bincode<T: Decode>(bytes: &[u8]) -> Option<T> {
+    bincode::decode_from_slice(bytes, standard())
+        .ok()
+        .map(|(one, _)| one)
+}
+
+/// `sources` of an item stored before version 5, filled by `fill_sources`
+const NO_SOURCES: Vec<ExternalSource> = Vec::new();
+
+/// the sources known from the `source` of an item stored before version 5
+fn sources_of(source: &Option<String>) -> Vec<ExternalSource> {
+    source
+        .iter()
+        .filter_map(|url| ExternalSource::parse(url))
+        .collect()
+}
+
+impl Book {
+    fn fill_sources(mut self) -> Self {
+        self.sources = sources_of(&self.source);
+        self
+    }
+}
+
+impl Movie {
+    fn fill_sources(mut self) -> Self {
+        self.sources = sources_of(&self.source);
+        self
+    }
+}
+
+impl AlbumV5 {
+    fn fill_sources(mut self) -> Self {
+        self.sources = sources_of(&self.source);
+        self
+    }
+}
+
+/// Decode `bytes` of a version before the last fields were added, with `appended` as
+/// their value, a tuple for several fields.
+fn decode_appended<T: Decode, A: Encode>(bytes: &[u8], appended: &A) -> Option<T> {
+    let mut bytes = bytes.to_vec();
+    bytes.extend(bincode::encode_to_vec(appended, standard()).unwrap());
+    decode_bincode(&bytes)
+}
+
+impl Versioned for Book {
+    /// 1: `price_value`, `page_count`, `pub_date`, `isbn13` and `isbn_invalid` appended
+    /// 2: text without markup, `description_html` and `content_html` appended
+    /// 3: `authors` and `translators` as [Person]
+    /// 4: `rating` appended
+    /// 5: `sources` appended
+    const VERSION: u8 = 5;
+
+    fn decode_old(version: u8, bytes: &[u8]) -> Option<Self> {
+        match version {
+            // fields were only appended, so a value with the later ones still decodes, and
+            // the ones added in 1 are derived
+            0 | 1 => decode_bincode::<BookV0>(bytes).map(Book::from),
+            2 => decode_bincode::<BookV2>(bytes).map(Book::from),
+            3 => decode_appended(bytes, &(Rating::default(), NO_SOURCES)).map(Self::fill_sources),
+            4 => decode_appended(bytes, &NO_SOURCES).map(Self::fill_sources),
+            _ => None,
+        }
+    }
+}
+
+impl Versioned for Movie {
+    /// 1: `runtime_minutes`, `episode_runtime_minutes` and `release_dates_parsed` appended
+    /// 2: text without markup, `description_html` appended
+    /// 3: `directors`, `writers` and `stars` as [Person]
+    /// 4: `rating` appended
+    /// 5: `sources` appended
+    const VERSION: u8 = 5;
+
+    fn decode_old(version: u8, bytes: &[u8]) -> Option<Self> {
+        match version {
+            0 | 1 => decode_bincode::<MovieV0>(bytes).map(Movie::from),
+            2 => decode_bincode::<MovieV2>(bytes).map(Movie::from),
+            3 => decode_appended(bytes, &(Rating::default(), NO_SOURCES)).map(Self::fill_sources),
+            4 => decode_appended(bytes, &NO_SOURCES).map(Self::fill_sources),
+            _ => None,
+        }
+    }
+}
+
+impl Versioned for Album {
+    /// 1: same fields, with the version prefix
+    /// 2: text without markup, `description_html` and `content_html` appended
+    /// 3: `artists` as [Person]
+    /// 4: `rating` appended
+    /// 5: `sources` appended
+    /// 6: `tracks` as [Track], `pub_date` and `genres` appended
+    const VERSION: u8 = 6;
+
+    fn decode_old(version: u8, bytes: &[u8]) -> Option<Self> {
+        let v5 = match version {
+            0 | 1 => decode_bincode::<AlbumV1>(bytes).map(AlbumV5::from),
+            2 => decode_bincode::<AlbumV2>(bytes).map(AlbumV5::from),
+            3 => decode_appended::<AlbumV5, _>(bytes, &(Rating::default(), NO_SOURCES))
+                .map(AlbumV5::fill_sources),
+            4 => decode_appended::<AlbumV5, _>(bytes, &NO_SOURCES).map(AlbumV5::fill_sources),
+            5 => decode_bincode::<AlbumV5>(bytes),
+            _ => None,
+        };
+        v5.map(Album::from)
+    }
+}
+
+/// [Book] before versioning, the fields of version 1 are derived from these
+#[derive(Decode)]
+struct BookV0 {
+    title: String,
+    cover: Option<String>,
+    source: Option<String>,
+    isbn: Option<String>,
+    authors: Vec<String>,
+    publisher: Option<String>,
+    subtitle: Option<String>,
+    translators: Vec<String>,
+    original_title: Option<String>,
+    language: Option<String>,
+    pub_time: Option<String>,
+    bookformat: Option<String>,
+    price: Option<String>,
+    pages: Option<String>,
+    other_info: Option<String>,
+    tags: Vec<String>,
+    description: Option<String>,
+    content: Option<String>,
+}
+
+impl From<BookV0> for Book {
+    fn from(old: BookV0) -> Self {
+        let isbn13 = old.isbn.as_deref().and_then(to_isbn13);
+        Book {
+            rating: Rating::default(),
+            sources: sources_of(&old.source),
+            price_value: old.price.as_deref().and_then(parse_price),
+            page_count: old.pages.as_deref().and_then(parse_pages),
+            pub_date: old.pub_time.as_deref().and_then(parse_date),
+            isbn_invalid: old.isbn.is_some() && isbn13.is_none(),
+            isbn13,
+            description_html: old.description.as_deref().map(sanitize_str),
+            content_html: old.content.as_deref().map(sanitize_str),
+            title: plain_str(&old.title),
+            cover: old.cover,
+            source: old.source,
+            isbn: old.isbn,
+            authors: named(plain_all(old.authors)),
+            publisher: old.publisher.as_deref().map(plain_str),
+            subtitle: old.subtitle.as_deref().map(plain_str),
+            translators: named(plain_all(old.translators)),
+            original_title: old.original_title.as_deref().map(plain_str),
+            language: old.language,
+            pub_time: old.pub_time,
+            bookformat: old.bookformat,
+            price: old.price,
+            pages: old.pages,
+            other_info: old.other_info.as_deref().map(block_str),
+            tags: plain_all(old.tags),
+            description: old.description.as_deref().map(block_str),
+            content: old.content.as_deref().map(block_str),
+        }
+    }
+}
+
+/// [Movie] before versioning, the fields of version 1 are derived from these
+#[derive(Decode)]
+struct MovieV0 {
+    title: String,
+    cover: Option<String>,
+    source: Option<String>,
+    imdb: Option<String>,
+    directors: Vec<String>,
+    writers: Vec<String>,
+    stars: Vec<String>,
+    genres: Vec<String>,
+    countries: Vec<String>,
+    languages: Vec<String>,
+    runtime: Option<String>,
+    season: Option<String>,
+    episode: Option<String>,
+    episode_runtime: Option<String>,
+    release_dates: Vec<String>,
+    alias: Vec<String>,
+    tags: Vec<String>,
+    description: Option<String>,
+}
+
+impl From<MovieV0> for Movie {
+    fn from(old: MovieV0) -> Self {
+        Movie {
+            rating: Rating::default(),
+            sources: sources_of(&old.source),
+            runtime_minutes: old.runtime.as_deref().and_then(parse_minutes),
+            episode_runtime_minutes: old.episode_runtime.as_deref().and_then(parse_minutes),
+            release_dates_parsed: old.release_dates.iter().map(|d| parse_date(d)).collect(),
+            description_html: old.description.as_deref().map(sanitize_str),
+            title: plain_str(&old.title),
+            cover: old.cover,
+            source: old.source,
+            imdb: old.imdb,
+            directors: named(plain_all(old.directors)),
+            writers: named(plain_all(old.writers)),
+            stars: named(plain_all(old.stars)),
+            genres: old.genres,
+            countries: old.countries,
+            languages: old.languages,
+            runtime: old.runtime,
+            season: old.season,
+            episode: old.episode,
+            episode_runtime: old.episode_runtime,
+            release_dates: old.release_dates,
+            alias: plain_all(old.alias),
+            tags: plain_all(old.tags),
+            description: old.description.as_deref().map(block_str),
+        }
+    }
+}
+
+/// [Album] up to version 1
+#[derive(Decode)]
+struct AlbumV1 {
+    title: String,
+    cover: Option<String>,
+    source: Option<String>,
+    artists: Vec<String>,
+    companies: Vec<String>,
+    pub_time: Option<String>,
+    genre: Option<String>,
+    medium: Option<String>,
+    code: Option<String>,
+    format: Option<String>,
+    tags: Vec<String>,
+    description: Option<String>,
+    content: Option<String>,
+    tracks: Vec<String>,
+}
+
+impl From<AlbumV1> for AlbumV5 {
+    fn from(old: AlbumV1) -> Self {
+        AlbumV5 {
+            rating: Rating::default(),
+            sources: sources_of(&old.source),
+            description_html: old.description.as_deref().map(sanitize_str),
+            content_html: old.content.as_deref().map(sanitize_str),
+            title: plain_str(&old.title),
+            cover: old.cover,
+            source: old.source,
+            artists: named(plain_all(old.artists)),
+            companies: plain_all(old.companies),
+            pub_time: old.pub_time,
+            genre: old.genre,
+            medium: old.medium,
+            code: old.code,
+            format: old.format,
+            tags: plain_all(old.tags),
+            description: old.description.as_deref().map(block_str),
+            content: old.content.as_deref().map(block_str),
+            tracks: plain_all(old.tracks),
+        }
+    }
+}
+
+/// values stored before version 2 kept the markup of the page, eg. `&amp;`
+fn plain_all(values: Vec<String>) -> Vec<String> {
+    values.iter().map(|v| plain_str(v)).collect()
+}
+
+/// people stored before version 3, by name only
+fn named(names: Vec<String>) -> Vec<Person> {
+    names.into_iter().map(Person::named).collect()
+}
+
+/// [Book] of version 2
+#[derive(Decode)]
+struct BookV2 {
+    title: String,
+    cover: Option<String>,
+    source: Option<String>,
+    isbn: Option<String>,
+    authors: Vec<String>,
+    publisher: Option<String>,
+    subtitle: Option<String>,
+    translators: Vec<String>,
+    original_title: Option<String>,
+    language: Option<String>,
+    pub_time: Option<String>,
+    bookformat: Option<String>,
+    price: Option<String>,
+    pages: Option<String>,
+    other_info: Option<String>,
+    tags: Vec<String>,
+    description: Option<String>,
+    content: Option<String>,
+    price_value: Option<Price>,
+    page_count: Option<u32>,
+    pub_date: Option<PartialDate>,
+    isbn13: Option<String>,
+    isbn_invalid: bool,
+    description_html: Option<String>,
+    content_html: Option<String>,
+}
+
+impl From<BookV2> for Book {
+    fn from(old: BookV2) -> Self {
+        Book {
+            rating: Rating::default(),
+            sources: sources_of(&old.source),
+            title: old.title,
+            cover: old.cover,
+            source: old.source,
+            isbn: old.isbn,
+            authors: named(old.authors),
+            publisher: old.publisher,
+            subtitle: old.subtitle,
+            translators: named(old.translators),
+            original_title: old.original_title,
+            language: old.language,
+            pub_time: old.pub_time,
+            bookformat: old.bookformat,
+            price: old.price,
+            pages: old.pages,
+            other_info: old.other_info,
+            tags: old.tags,
+            description: old.description,
+            content: old.content,
+            price_value: old.price_value,
+            page_count: old.page_count,
+            pub_date: old.pub_date,
+            isbn13: old.isbn13,
+            isbn_invalid: old.isbn_invalid,
+            description_html: old.description_html,
+            content_html: old.content_html,
+        }
+    }
+}
+
+/// [Movie] of version 2
+#[derive(Decode)]
+struct MovieV2 {
+    title: String,
+    cover: Option<String>,
+    source: Option<String>,
+    imdb: Option<String>,
+    directors: Vec<String>,
+    writers: Vec<String>,
+    stars: Vec<String>,
+    genres: Vec<String>,
+    countries: Vec<String>,
+    languages: Vec<String>,
+    runtime: Option<String>,
+    season: Option<String>,
+    episode: Option<String>,
+    episode_runtime: Option<String>,
+    release_dates: Vec<String>,
+    alias: Vec<String>,
+    tags: Vec<String>,
+    description: Option<String>,
+    runtime_minutes: Option<u32>,
+    episode_runtime_minutes: Option<u32>,
+    release_dates_parsed: Vec<Option<PartialDate>>,
+    description_html: Option<String>,
+}
+
+impl From<MovieV2> for Movie {
+    fn from(old: MovieV2) -> Self {
+        Movie {
+            rating: Rating::default(),
+            sources: sources_of(&old.source),
+            title: old.title,
+            cover: old.cover,
+            source: old.source,
+            imdb: old.imdb,
+            directors: named(old.directors),
+            writers: named(old.writers),
+            stars: named(old.stars),
+            genres: old.genres,
+            countries: old.countries,
+            languages: old.languages,
+            runtime: old.runtime,
+            season: old.season,
+            episode: old.episode,
+            episode_runtime: old.episode_runtime,
+            release_dates: old.release_dates,
+            alias: old.alias,
+            tags: old.tags,
+            description: old.description,
+            runtime_minutes: old.runtime_minutes,
+            episode_runtime_minutes: old.episode_runtime_minutes,
+            release_dates_parsed: old.release_dates_parsed,
+            description_html: old.description_html,
+        }
+    }
+}
+
+/// [Album] of version 2
+#[derive(Decode)]
+struct AlbumV2 {
+    title: String,
+    cover: Option<String>,
+    source: Option<String>,
+    artists: Vec<String>,
+    companies: Vec<String>,
+    pub_time: Option<String>,
+    genre: Option<String>,
+    medium: Option<String>,
+    code: Option<String>,
+    format: Option<String>,
+    tags: Vec<String>,
+    description: Option<String>,
+    content: Option<String>,
+    tracks: Vec<String>,
+    description_html: Option<String>,
+    content_html: Option<String>,
+}
+
+impl From<AlbumV2> for AlbumV5 {
+    fn from(old: AlbumV2) -> Self {
+        AlbumV5 {
+            rating: Rating::default(),
+            sources: sources_of(&old.source),
+            title: old.title,
+            cover: old.cover,
+            source: old.source,
+            artists: named(old.artists),
+            companies: old.companies,
+            pub_time: old.pub_time,
+            genre: old.genre,
+            medium: old.medium,
+            code: old.code,
+            format: old.format,
+            tags: old.tags,
+            description: old.description,
+            content: old.content,
+            tracks: old.tracks,
+            description_html: old.description_html,
+            content_html: old.content_html,
+        }
+    }
+}
+
+/// [Album] of version 5, to which the former versions are converted first
+#[derive(Decode)]
+struct AlbumV5 {
+    title: String,
+    cover: Option<String>,
+    source: Option<String>,
+    artists: Vec<Person>,
+    companies: Vec<String>,
+    pub_time: Option<String>,
+    genre: Option<String>,
+    medium: Option<String>,
+    code: Option<String>,
+    format: Option<String>,
+    tags: Vec<String>,
+    description: Option<String>,
+    content: Option<String>,
+    tracks: Vec<String>,
+    description_html: Option<String>,
+    content_html: Option<String>,
+    rating: Rating,
+    sources: Vec<ExternalSource>,
+}
+
+impl From<AlbumV5> for Album {
+    fn from(old: AlbumV5) -> Self {
+        let titles = old.tracks.into_iter().map(|title| (title, None)).collect();
+        Album {
+            tracks: from_page(old.content.as_deref(), numbered(vec![titles])),
+            pub_date: old.pub_time.as_deref().and_then(parse_date),
+            genres: old.genre.as_deref().map(parse_genres).unwrap_or_default(),
+            title: old.title,
+            cover: old.cover,
+            source: old.source,
+            artists: old.artists,
+            companies: old.companies,
+            pub_time: old.pub_time,
+            genre: old.genre,
+            medium: old.medium,
+            code: old.code,
+            format: old.format,
+            tags: old.tags,
+            description: old.description,
+            content: old.content,
+            description_html: old.description_html,
+            content_html: old.content_html,
+            rating: old.rating,
+            sources: old.sources,
+        }
+    }
+}
+
+/// how many items of a kind [migrate] rewrote, and could not decode
+#[derive(Debug)]
+pub struct Migrated {
+    pub kind: &'static str,
+    pub total: usize,
+    pub migrated: usize,
+    pub failed: usize,
+}
+
+fn migrate_kind<T: Web>(db: &Db) -> Migrated {
+    let trees = Trees::open::<T>(db);
+    let mut migrated = 0;
+    let mut failed = 0;
+    for kv in trees.data.iter() {
+        let (k, v) = kv.unwrap();
+        if version(&v) == T::VERSION {
+            continue;
+        }
+        match decode::<T>(&v) {
+            Some(one) => {
+                trees.data.insert(k, encode(&one)).unwrap();
+                migrated += 1;
+            }
+            None => {
+                error!(
+                    "{} {}: undecodable, version {}",
+                    T::KIND,
+                    ivec_to_u32(&k),
+                    version(&v)
+                );
+                failed += 1;
+            }
+        }
+    }
+    // items of older versions may have been left out of the indexes, and indexes of before
+    // the keys were shared map a key to one id
+    let old_indexes = trees.indexes.iter().any(|tree| {
+        tree.iter()
+            .keys()
+            .any(|k| split_index_key(&k.unwrap()).is_none())
+    });
+    if migrated > 0 || old_indexes {
+        trees.reindex::<T>();
+    }
+    Migrated {
+        kind: T::KIND,
+        total: trees.data.len(),
+        migrated,
+        failed,
+    }
+}
+
+/// Rewrite the items of every kind stored in an older version to the current one.
+pub fn migrate(db: &Db) -> Vec<Migrated> {
+    vec![
+        migrate_kind::<Movie>(db),
+        migrate_kind::<Book>(db),
+        migrate_kind::<Album>(db),
+    ]
+}
@@ -0,0 +1,193 @@
+//! Local full-text search over the stored items.
+//!
+//! Latin text is split into lowercase words, runs of CJK characters into overlapping
+//! bigrams, so `三体问题` is found by `三体`, and a single character by the bigrams it
+//! starts. For each kind, the `{kind}_fts` tree holds the
+//! postings `token 0x00 id -> weighted term frequency`, and `{kind}_fts_docs` the tokens of
+//! every item, to drop its old postings when it is fetched again.
+
+use crate::{
+    download::{title, u32_to_ivec, Trees, Web},
+    extract::{Album, Book, Movie},
+};
+use bincode::config::standard;
+use sled::{Db, Tree};
+use std::collections::HashMap;
+
+/// a ranked result of [search]
+#[derive(Debug)]
+pub struct Hit {
+    pub kind: &'static str,
+    pub id: u32,
+    pub title: String,
+    pub score: f64,
+}
+
+fn is_cjk(c: char) -> bool {
+    // the katakana middle dot separates words, as in `ハリー・ポッター`
+    c != '\u{30fb}'
+        && matches!(c,
+            '\u{3040}'..='\u{30ff}' // hiragana, katakana
+            | '\u{3400}'..='\u{4dbf}' // cjk extension a
+            | '\u{4e00}'..='\u{9fff}' // cjk unified ideographs
+            | '\u{ac00}'..='\u{d7af}' // hangul syllables
+            | '\u{f900}'..='\u{faff}' // cjk compatibility ideographs
+            | '\u{20000}'..='\u{2ebef}' // cjk extension b to f
+        )
+}
+
+/// Split `text` into lowercase words and CJK bigrams. A lone CJK character is kept as is.
+pub fn tokenize(text: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut word = String::new();
+    let mut cjk: Vec<char> = vec![];
+
+    let flush_cjk = |cjk: &mut Vec<char>, tokens: &mut Vec<String>| {
+        if cjk.len() == 1 {
+            tokens.push(cjk[0].to_string());
+        } else {
+            for pair in cjk.windows(2) {
+                tokens.push(pair.iter().collect());
+            }
+        }
+        cjk.clear();
+    };
+
+    for c in text.chars() {
+        if is_cjk(c) {
+            if !word.is_empty() {
+                tokens.push(std::mem::take(&mut word));
+            }
+            cjk.push(c);
+        } else if c.is_alphanumeric() {
+            if !cjk.is_empty() {
+                flush_cjk(&mut cjk, &mut tokens);
+            }
+            word.extend(c.to_lowercase());
+        } else {
+            if !word.is_empty() {
+                tokens.push(std::mem::take(&mut word));
+            }
+            if !cjk.is_empty() {
+                flush_cjk(&mut cjk, &mut tokens);
+            }
+        }
+    }
+    if !word.is_empty() {
+        tokens.push(word);
+    }
+    if !cjk.is_empty() {
+        flush_cjk(&mut cjk, &mut tokens);
+    }
+    tokens
+}
+
+fn posting_key(token: &str, id: u32) -> Vec<u8> {
+    let mut key = Vec::with_capacity(token.len() + 5);
+    key.extend_from_slice(token.as_bytes());
+    key.push(0);
+    key.extend_from_slice(&id.to_be_bytes());
+    key
+}
+
+/// Replace the postings of the item `id` with the tokens of `fields`, each `(text, weight)`.
+pub fn index_doc(fts: &Tree, docs: &Tree, id: u32, fields: &[(&str, u32)]) {
+    if let Some(v) = docs.get(u32_to_ivec(id)).unwrap() {
+        let (old, _): (Vec<String>, usize) = bincode::decode_from_slice(&v, standard()).unwrap();
+        for token in old {
+            fts.remove(posting_key(&token, id)).unwrap();
+        }
+    }
+
+    let mut tf: HashMap<String, u32> = HashMap::new();
+    for (text, weight) in fields {
+        for token in tokenize(text) {
+            *tf.entry(token).or_default() += weight;
+        }
+    }
+
+    let mut batch = sled::Batch::default();
+    for (token, n) in &tf {
+        batch.insert(posting_key(token, id), &n.to_be_bytes());
+    }
+    fts.apply_batch(batch).unwrap();
+
+    let tokens: Vec<String> = tf.into_keys().collect();
+    let encoded = bincode::encode_to_vec(tokens, standard()).unwrap();
+    docs.insert(u32_to_ivec(id), encoded).unwrap();
+}
+
+/// Score the items matching any token of `query`, with a BM25-like ranking: rarer tokens
+/// weigh more, repeated ones saturate.
+fn rank(fts: &Tree, docs: &Tree, query: &str) -> HashMap<u32, f64> {
+    let mut tokens = tokenize(query);
+    tokens.sort();
+    tokens.dedup();
+
+    let n = docs.len() as f64;
+    let mut scores: HashMap<u32, f64> = HashMap::new();
+    for token in tokens {
+        let postings = postings(fts, &token);
+        let df = postings.len() as f64;
+        let idf = (1.0 + (n - df + 0.5) / (df + 0.5)).ln();
+        for (id, tf) in postings {
+            *scores.entry(id).or_default() += idf * tf * 2.2 / (tf + 1.2);
+        }
+    }
+    scores
+}
+
+/// id -> term frequency of `token` in the items. A lone CJK character is only indexed by
+/// itself when it stands alone, so it also matches the bigrams it starts.
+fn postings(fts: &Tree, token: &str) -> HashMap<u32, f64> {
+    let mut prefix = token.as_bytes().to_vec();
+    let mut chars = token.chars();
+    if !matches!((chars.next(), chars.next()), (Some(c), None) if is_cjk(c)) {
+        prefix.push(0);
+    }
+    let mut postings: HashMap<u32, f64> = HashMap::new();
+    for kv in fts.scan_prefix(&prefix) {
+        let (k, v) = kv.unwrap();
+        let id = u32::from_be_bytes(k[k.len() - 4..].try_into().unwrap());
+        let tf = u32::from_be_bytes(v.as_ref().try_into().unwrap()) as f64;
+        *postings.entry(id).or_default() += tf;
+    }
+    postings
+}
+
+fn rank_kind<T: Web>(db: &Db, query: &str) -> Vec<(&'static str, u32, f64)> {
+    let trees = Trees::open::<T>(db);
+    rank(&trees.fts, &trees.fts_docs, query)
+        .into_iter()
+        .map(|(id, score)| (T::KIND, id, score))
+        .collect()
+}
+
+/// Search all kinds, or only `kind` (`movie`, `book` or `album`), best `limit` hits first.
+/// Only the items of the hits returned are read.
+pub fn search(db: &Db, query: &str, kind: Option<&str>, limit: usize) -> Vec<Hit> {
+    let wanted = |k: &str| kind.is_none_or(|kind| kind == k);
+    let mut ranked = vec![];
+    if wanted(Movie::KIND) {
+        ranked.extend(rank_kind::<Movie>(db, query));
+    }
+    if wanted(Book::KIND) {
+        ranked.extend(rank_kind::<Book>(db, query));
+    }
+    if wanted(Album::KIND) {
+        ranked.extend(rank_kind::<Album>(db, query));
+    }
+    ranked.sort_by(|a, b| b.2.total_cmp(&a.2).then(a.1.cmp(&b.1)));
+    ranked
+        .into_iter()
+        .filter_map(|(kind, id, score)| {
+            Some(Hit {
+                kind,
+                id,
+                title: title(db, kind, id)?,
+                score,
+            })
+        })
+        .take(limit)
+        .collect()
+}
@@ -0,0 +1,108 @@
+//! External sources of the items, eg. Douban, Goodreads, IMDb or Spotify, with the id of
+//! the item on each site taken from the url.
+
+use bincode::{Decode, Encode};
+use serde::Serialize;
+
+/// a page of the item on another site
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Serialize)]
+pub struct ExternalSource {
+    /// eg. `douban_book`, `imdb`, or the host for an unknown site
+    pub site: String,
+    pub url: String,
+    /// id of the item on `site`, if the url has one, eg. `tt1533117`
+    pub id: Option<String>,
+}
+
+impl ExternalSource {
+    /// The source of `url`, `None` if it is not an absolute http(s) url.
+    pub fn parse(url: &str) -> Option<Self> {
+        let url = url.trim();
+        let rest = url
+            .strip_prefix("https://")
+            .or_else(|| url.strip_prefix("http://"))?;
+        let (host, path) = rest.split_once('/').unwrap_or((rest, ""));
+        let host = host.to_lowercase();
+        let host = host.strip_prefix("www.").unwrap_or(&host);
+        if host.is_empty() {
+            return None;
+        }
+        let (path, query) = path.split_once('?').unwrap_or((path, ""));
+        let path = path.split('#').next().unwrap_or_default();
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+
+        let (site, id) = match host {
+            "book.douban.com" => ("douban_book", subject(&segments)),
+            "movie.douban.com" => ("douban_movie", subject(&segments)),
+            "music.douban.com" => ("douban_music", subject(&segments)),
+            "imdb.com" | "m.imdb.com" => ("imdb", after(&segments, "title")),
+            "goodreads.com" => (
+                "goodreads",
+                after(&segments, "show").map(|s| leading_digits(&s)),
+            ),
+            "open.spotify.com" => ("spotify", after(&segments, "album")),
+            "music.apple.com" => ("apple_music", segments.last().map(|s| s.to_string())),
+            "books.google.com" => ("google_books", query_param(query, "id")),
+            "themoviedb.org" => ("tmdb", tmdb(&segments)),
+            "bangumi.tv" | "bgm.tv" => ("bangumi", after(&segments, "subject")),
+            "discogs.com" => ("discogs", discogs(&segments)),
+            _ if host.ends_with(".bandcamp.com") => {
+                let artist = host.trim_end_matches(".bandcamp.com");
+                (
+                    "bandcamp",
+                    after(&segments, "album").map(|album| format!("{artist}/{album}")),
+                )
+            }
+            _ => (host, None),
+        };
+        Some(ExternalSource {
+            site: site.to_owned(),
+            url: url.to_owned(),
+            id: id.filter(|id| !id.is_empty()),
+        })
+    }
+}
+
+/// the segment after `name`, eg. `tt1533117` of `/title/tt1533117/`
+fn after(segments: &[&str], name: &str) -> Option<String> {
+    let i = segments.iter().position(|s| *s == name)?;
+    segments.get(i + 1).map(|s| s.to_string())
+}
+
+/// `2567698` of douban `/subject/2567698/`
+fn subject(segments: &[&str]) -> Option<String> {
+    after(segments, "subject").filter(|id| id.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// `18373` of goodreads `18373.Flowers_for_Algernon`
+fn leading_digits(s: &str) -> String {
+    s.chars().take_while(|c| c.is_ascii_digit()).collect()
+}
+
+/// `movie/550` or `tv/1396` of `/movie/550-fight-club`
+fn tmdb(segments: &[&str]) -> Option<String> {
+    let (kind, id) = match segments {
+        [kind, id, ..] if ["movie", "tv"].contains(kind) => (kind, leading_digits(id)),
+        _ => return None,
+    };
+    Some(format!("{kind}/{id}")).filter(|_| !id.is_empty())
+}
+
+/// `master/33248` or `release/249504` of `/master/33248-Radiohead-OK-Computer`
+fn discogs(segments: &[&str]) -> Option<String> {
+    segments.windows(2).find_map(|pair| match pair {
+        [kind, id] if ["master", "release"].contains(kind) => {
+            let id = leading_digits(id);
+            (!id.is_empty()).then(|| format!("{kind}/{id}"))
+        }
+        _ => None,
+    })
+}
+
+fn query_param(query: &str, name: &str) -> Option<String> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(k, _)| *k == name)
+        .map(|(_, v)| v.to_owned())
+}
@@ -0,0 +1,168 @@
+//! Plain text out of the markup of the pages: entities decoded, tags stripped and
+//! whitespace collapsed, with a sanitized html version for the descriptions.
+
+use scraper::{node::Node, ElementRef, Html};
+
+/// elements starting a new paragraph in [block]
+const BLOCKS: [&str; 14] = [
+    "p",
+    "div",
+    "section",
+    "blockquote",
+    "ul",
+    "ol",
+    "li",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "pre",
+];
+
+/// elements kept by [sanitize], without their attributes but the `href` of links
+const ALLOWED: [&str; 17] = [
+    "p",
+    "br",
+    "b",
+    "strong",
+    "i",
+    "em",
+    "u",
+    "a",
+    "ul",
+    "ol",
+    "li",
+    "blockquote",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "pre",
+];
+
+/// elements dropped with everything in them
+const DROPPED: [&str; 5] = ["script", "style", "iframe", "object", "template"];
+
+/// The text of `ele` on one line, eg. a title or a name.
+pub fn plain(ele: &ElementRef) -> String {
+    collapse(&ele.text().collect::<String>())
+}
+
+/// The text of `ele` with `<br>` as line breaks and paragraphs separated by an empty line,
+/// eg. a description.
+pub fn block(ele: &ElementRef) -> String {
+    let mut raw = String::new();
+    push_block(ele, &mut raw);
+
+    let mut out = String::new();
+    let mut blank = false;
+    for line in raw.split('\n').map(collapse) {
+        if line.is_empty() {
+            blank = !out.is_empty();
+            continue;
+        }
+        if !out.is_empty() {
+            out.push_str(if blank { "\n\n" } else { "\n" });
+        }
+        out.push_str(&line);
+        blank = false;
+    }
+    out
+}
+
+fn push_block(ele: &ElementRef, out: &mut String) {
+    for child in ele.children() {
+        match child.value() {
+            // line breaks of the source are only whitespace
+            Node::Text(text) => out.push_str(&text.replace(['\n', '\r'], " ")),
+            Node::Element(e) => {
+                let child = ElementRef::wrap(child).unwrap();
+                match e.name() {
+                    name if DROPPED.contains(&name) => {}
+                    "br" => out.push('\n'),
+                    name if BLOCKS.contains(&name) => {
+                        out.push_str("\n\n");
+                        push_block(&child, out);
+                        out.push_str("\n\n");
+                    }
+                    _ => push_block(&child, out),
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The inner html of `ele` with only the [ALLOWED] elements, no attributes but the `href`
+/// of http(s) or relative links, and whitespace collapsed.
+pub fn sanitize(ele: &ElementRef) -> String {
+    let mut out = String::new();
+    push_sanitized(ele, &mut out);
+    collapse(&out)
+}
+
+fn push_sanitized(ele: &ElementRef, out: &mut String) {
+    for child in ele.children() {
+        match child.value() {
+            Node::Text(text) => out.push_str(&escape(text)),
+            Node::Element(e) => {
+                let child = ElementRef::wrap(child).unwrap();
+                let name = e.name();
+                if DROPPED.contains(&name) {
+                    continue;
+                }
+                if !ALLOWED.contains(&name) {
+                    push_sanitized(&child, out);
+                    continue;
+                }
+                out.push('<');
+                out.push_str(name);
+                if name == "a" {
+                    if let Some(href) = e.attr("href").filter(|href| safe_href(href)) {
+                        out.push_str(&format!(r#" href="{}""#, escape(href)));
+                    }
+                }
+                out.push('>');
+                if name != "br" {
+                    push_sanitized(&child, out);
+                    out.push_str(&format!("</{name}>"));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn safe_href(href: &str) -> bool {
+    let href = href.trim().to_lowercase();
+    href.starts_with("http://") || href.starts_with("https://") || href.starts_with('/')
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// whitespace runs as one space, trimmed
+fn collapse(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// [plain] of an html fragment, eg. a value stored before the text was cleaned up
+pub fn plain_str(html: &str) -> String {
+    plain(&Html::parse_fragment(html).root_element())
+}
+
+/// [block] of an html fragment
+pub fn block_str(html: &str) -> String {
+    block(&Html::parse_fragment(html).root_element())
+}
+
+/// [sanitize] of an html fragment
+pub fn sanitize_str(html: &str) -> String {
+    sanitize(&Html::parse_fragment(html).root_element())
+}
@@ -0,0 +1,179 @@
+//! Track listings of the albums, from the track list text of the page, eg.
+//! `Disc 2` / `1. Airbag (4:44)`, or from its track elements.
+
+use crate::normalize::parse_duration;
+use bincode::{Decode, Encode};
+use serde::Serialize;
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Serialize)]
+pub struct Track {
+    /// from 1
+    pub disc: u32,
+    /// from 1 on each disc
+    pub number: u32,
+    pub title: String,
+    /// in seconds
+    pub duration: Option<u32>,
+}
+
+/// Tracks numbered in order on each disc, from their title and duration.
+pub fn numbered(discs: Vec<Vec<(String, Option<u32>)>>) -> Vec<Track> {
+    let mut tracks = vec![];
+    for (disc, titles) in discs.into_iter().filter(|d| !d.is_empty()).enumerate() {
+        for (i, (title, duration)) in titles.into_iter().enumerate() {
+            tracks.push(Track {
+                disc: disc as u32 + 1,
+                number: i as u32 + 1,
+                title,
+                duration,
+            });
+        }
+    }
+    tracks
+}
+
+/// The tracks of the track list text `content` if it lists the same tracks as `elements`,
+/// the ones of the track elements, as it has the numbers, discs and durations. Without
+/// track elements, only a list numbered in order on each disc is taken.
+pub fn from_page(content: Option<&str>, elements: Vec<Track>) -> Vec<Track> {
+    let listed = content.map(parse_track_list).unwrap_or_default();
+    let consistent = if elements.is_empty() {
+        in_order(&listed)
+    } else {
+        listed.len() == elements.len()
+    };
+    if !listed.is_empty() && consistent {
+        listed
+    } else {
+        elements
+    }
+}
+
+/// numbered 1, 2, 3... on each disc, the discs in order
+fn in_order(tracks: &[Track]) -> bool {
+    let mut last = (1, 0);
+    for track in tracks {
+        let expected = if track.disc == last.0 {
+            (last.0, last.1 + 1)
+        } else {
+            (last.0 + 1, 1)
+        };
+        if (track.disc, track.number) != expected {
+            return false;
+        }
+        last = expected;
+    }
+    true
+}
+
+/// Parse a track list, one track per line, eg. `1. Airbag 4:44`, `2-03 Nude (4:15)`,
+/// `A1 Speak to Me`. Lines like `CD 2` or `Disc 2` start a disc; the other lines without
+/// a track number are skipped.
+pub fn parse_track_list(text: &str) -> Vec<Track> {
+    let mut tracks: Vec<Track> = vec![];
+    let mut disc = 1;
+    for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        if let Some(n) = disc_header(line) {
+            disc = n;
+            continue;
+        }
+        let Some((line_disc, number, rest)) = track_number(line) else {
+            continue;
+        };
+        let disc = line_disc.unwrap_or(disc);
+        let number =
+            number.unwrap_or_else(|| tracks.iter().filter(|t| t.disc == disc).count() as u32 + 1);
+        let (title, duration) = split_duration(rest);
+        if title.is_empty() {
+            continue;
+        }
+        tracks.push(Track {
+            disc,
+            number,
+            title,
+            duration,
+        });
+    }
+    tracks
+}
+
+/// `2` of `CD 2`, `Disc 2:`, `disk2`, `碟2`
+fn disc_header(line: &str) -> Option<u32> {
+    let lower = line.to_lowercase();
+    let rest = ["disc", "disk", "cd", "碟"]
+        .iter()
+        .find_map(|prefix| lower.strip_prefix(prefix))?;
+    let rest = rest.trim().trim_end_matches([':', '：', '.']).trim();
+    rest.parse().ok().filter(|n| *n > 0)
+}
+
+/// highest disc and track number taken as such, a larger one is part of a title
+const MAX_NUMBER: u32 = 99;
+
+/// The disc and number of a track line, and the rest of it: `1. Airbag`, `3 - Nude`,
+/// `01 Airbag`, `2-03 Nude` (disc 2, number 3), `A1 Speak to Me` (a vinyl side, no
+/// number). A number without separator or out of range, as in `99 Luftballons` or
+/// `1984 (Live)`, and a decimal, as in `2.03 Nude`, are part of a title.
+fn track_number(line: &str) -> Option<(Option<u32>, Option<u32>, &str)> {
+    let digits = leading_digits(line);
+    if digits == 0 {
+        // vinyl side, `A1` or `B2.`
+        let side = line.chars().next()?;
+        let rest = &line[side.len_utf8()..];
+        let n = leading_digits(rest);
+        if !('A'..='H').contains(&side) || !(1..=2).contains(&n) {
+            return None;
+        }
+        return Some((None, None, separated(&rest[n..], true)?));
+    }
+    let first = &line[..digits];
+    let rest = &line[digits..];
+    // `2-03 Nude`, `1-01. In the Flesh?`
+    if let Some(after) = rest.strip_prefix('-') {
+        let n = leading_digits(after);
+        if n > 0 {
+            let disc = in_range(first)?;
+            let number = in_range(&after[..n])?;
+            return Some((Some(disc), Some(number), separated(&after[n..], true)?));
+        }
+    }
+    // a zero-padded number needs no separator
+    let padded = digits > 1 && first.starts_with('0');
+    Some((None, Some(in_range(first)?), separated(rest, padded)?))
+}
+
+fn leading_digits(s: &str) -> usize {
+    s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len())
+}
+
+fn in_range(digits: &str) -> Option<u32> {
+    digits.parse().ok().filter(|n| (1..=MAX_NUMBER).contains(n))
+}
+
+/// The text after the separator of a track number, `.`, `)`, `、`, `:` or a dash, `None` if
+/// there is none or it is a decimal point. With `space`, whitespace is enough.
+fn separated(s: &str, space: bool) -> Option<&str> {
+    if let Some(rest) = s.strip_prefix(['.', ')', '、', ':', '：', '．']) {
+        // `2.03`, `4:44`
+        if rest.starts_with(|c: char| c.is_ascii_digit()) {
+            return None;
+        }
+        return Some(rest.trim());
+    }
+    let trimmed = s.trim_start();
+    if let Some(rest) = trimmed.strip_prefix(['-', '–', '—']) {
+        return Some(rest.trim());
+    }
+    (space && trimmed.len() < s.len()).then_some(trimmed)
+}
+
+/// `Airbag` and 284 of `Airbag 4:44`, `Airbag (4:44)` or `Airbag - 4:44`
+fn split_duration(s: &str) -> (String, Option<u32>) {
+    if let Some((title, last)) = s.rsplit_once(char::is_whitespace) {
+        if let Some(duration) = parse_duration(last) {
+            let title = title.trim().trim_end_matches(['-', '–', '—', '|']).trim();
+            return (title.to_owned(), Some(duration));
+        }
+    }
+    (s.trim().to_owned(), None)
+}
@@ -0,0 +1,263 @@
+//! Consistency checks of the db and the cover directories, with optional repair.
+
+use crate::{
+    download::{index_key, ivec_to_u32, split_index_key, u32_to_ivec, Trees, Validators, Web},
+    ratings,
+    runs::Run,
+    schema::{decode, version, Versioned},
+};
+use bincode::config::standard;
+use sled::{Db, IVec, Tree};
+use std::{collections::HashSet, fmt, fs, path::PathBuf};
+
+/// a problem found by [verify]
+#[derive(Debug)]
+pub enum Issue {
+    /// the value of `key` in `tree` cannot be decoded
+    Undecodable { tree: String, key: IVec },
+    /// the value of `key` in `tree` was written by a newer version, it is never removed
+    NewerVersion {
+        tree: String,
+        key: IVec,
+        version: u8,
+    },
+    /// the key in `tree` is not an id
+    BadKey { tree: String, key: IVec },
+    /// the id is in `{kind}_covers`, but there is no file for it
+    MissingCover { kind: &'static str, id: u32 },
+    /// a file in the cover directory, not recorded in `{kind}_covers`
+    OrphanCover { kind: &'static str, path: PathBuf },
+    /// the id is both stored and in `{kind}_404`
+    StoredAndNotFound { kind: &'static str, id: u32 },
+    /// the index entry points to an id which is not stored
+    DanglingIndex { tree: String, key: String, id: u32 },
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Issue::Undecodable { tree, key } => write!(f, "{tree}: undecodable value of {key:?}"),
+            Issue::NewerVersion { tree, key, version } => {
+                write!(
+                    f,
+                    "{tree}: {key:?} is of the newer version {version}, left as is"
+                )
+            }
+            Issue::BadKey { tree, key } => write!(f, "{tree}: bad key {key:?}"),
+            Issue::MissingCover { kind, id } => write!(f, "{kind}_covers: no file for {id}"),
+            Issue::OrphanCover { kind, path } => {
+                write!(f, "{kind}_covers: {} not recorded", path.display())
+            }
+            Issue::StoredAndNotFound { kind, id } => write!(f, "{kind}_404: {id} is stored"),
+            Issue::DanglingIndex { tree, key, id } => {
+                write!(f, "{tree}: {key} points to {id}, not stored")
+            }
+        }
+    }
+}
+
+/// Check the trees of `T` and its covers in `cover_path`. With `repair`, bad entries are
+/// removed so the next crawl fetches them again; cover files and items of a newer version
+/// than [Versioned::VERSION] are never deleted.
+pub fn verify<T: Web>(db: &Db, cover_path: &str, repair: bool) -> Vec<Issue> {
+    let trees = Trees::open::<T>(db);
+    let mut issues = vec![];
+    let mut bad_items = false;
+
+    // items
+    for kv in trees.data.iter() {
+        let (k, v) = kv.unwrap();
+        if k.len() != 4 {
+            issues.push(bad_key(&trees.data, k));
+        } else if version(&v) > T::VERSION {
+            issues.push(newer_version(&trees.data, k, &v));
+        } else if decode::<T>(&v).is_none() {
+            if repair {
+                trees.etags.remove(&k).unwrap();
+            }
+            bad_items = true;
+            issues.push(Issue::Undecodable {
+                tree: tree_name(&trees.data),
+                key: k,
+            });
+        }
+    }
+
+    // id sets, whose values are not checked
+    for tree in [&trees.not_found, &trees.covers, &trees.cover_too_large] {
+        for k in tree.iter().keys() {
+            let k = k.unwrap();
+            if k.len() != 4 {
+                issues.push(bad_key(tree, k));
+            }
+        }
+    }
+
+    for tree in [&trees.etags, &trees.cover_etags] {
+        for kv in tree.iter() {
+            let (k, v) = kv.unwrap();
+            if k.len() != 4 {
+                issues.push(bad_key(tree, k));
+            } else if bincode::decode_from_slice::<Validators, _>(&v, standard()).is_err() {
+                issues.push(Issue::Undecodable {
+                    tree: tree_name(tree),
+                    key: k,
+                });
+            }
+        }
+    }
+
+    for kv in trees.ratings.iter() {
+        let (k, v) = kv.unwrap();
+        if !ratings::is_key(&k) {
+            issues.push(bad_key(&trees.ratings, k));
+        } else if !ratings::is_value(&v) {
+            issues.push(Issue::Undecodable {
+                tree: tree_name(&trees.ratings),
+                key: k,
+            });
+        }
+    }
+
+    for kv in trees.fts_docs.iter() {
+        let (k, v) = kv.unwrap();
+        if bincode::decode_from_slice::<Vec<String>, _>(&v, standard()).is_err() {
+            // its postings are dropped by the rebuild below
+            bad_items = true;
+            issues.push(Issue::Undecodable {
+                tree: tree_name(&trees.fts_docs),
+                key: k,
+            });
+        }
+    }
+
+    for tree in &trees.indexes {
+        for k in tree.iter().keys() {
+            let k = k.unwrap();
+            let Some((key, id)) = split_index_key(&k) else {
+                // eg. a key -> id entry of before the keys were shared, rebuilt below
+                bad_items = true;
+                issues.push(bad_key(tree, k));
+                continue;
+            };
+            if !trees.data.contains_key(u32_to_ivec(id)).unwrap() {
+                issues.push(Issue::DanglingIndex {
+                    tree: tree_name(tree),
+                    key: String::from_utf8_lossy(key).into_owned(),
+                    id,
+                });
+            }
+        }
+    }
+
+    for k in trees.not_found.iter().keys() {
+        let k = k.unwrap();
+        if k.len() == 4 && trees.data.contains_key(&k).unwrap() {
+            issues.push(Issue::StoredAndNotFound {
+                kind: T::KIND,
+                id: ivec_to_u32(&k),
+            });
+        }
+    }
+
+    // covers on disk, named `{id}.{ext}`
+    let mut on_disk = HashSet::new();
+    if let Ok(dir) = fs::read_dir(cover_path) {
+        for entry in dir {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|ext| ext == "part") {
+                continue;
+            }
+            let id = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .and_then(|s| s.parse::<u32>().ok());
+            match id {
+                Some(id) if trees.covers.contains_key(u32_to_ivec(id)).unwrap() => {
+                    on_disk.insert(id);
+                }
+                _ => issues.push(Issue::OrphanCover {
+                    kind: T::KIND,
+                    path,
+                }),
+            }
+        }
+    }
+    for k in trees.covers.iter().keys() {
+        let k = k.unwrap();
+        if k.len() == 4 && !on_disk.contains(&ivec_to_u32(&k)) {
+            issues.push(Issue::MissingCover {
+                kind: T::KIND,
+                id: ivec_to_u32(&k),
+            });
+        }
+    }
+
+    if repair {
+        for issue in &issues {
+            match issue {
+                Issue::BadKey { tree, key } | Issue::Undecodable { tree, key } => {
+                    db.open_tree(tree).unwrap().remove(key).unwrap();
+                }
+                Issue::MissingCover { id, .. } => {
+                    trees.covers.remove(u32_to_ivec(*id)).unwrap();
+                    trees.cover_etags.remove(u32_to_ivec(*id)).unwrap();
+                }
+                Issue::StoredAndNotFound { id, .. } => {
+                    trees.not_found.remove(u32_to_ivec(*id)).unwrap();
+                }
+                Issue::DanglingIndex { tree, key, id } => {
+                    let tree = db.open_tree(tree).unwrap();
+                    tree.remove(index_key(key, *id)).unwrap();
+                }
+                Issue::OrphanCover { .. } | Issue::NewerVersion { .. } => {}
+            }
+        }
+        if bad_items {
+            trees.reindex::<T>();
+        }
+    }
+
+    issues
+}
+
+/// Check that every run can be decoded, removing the bad ones with `repair`, but not those of
+/// a newer version.
+pub fn verify_runs(db: &Db, repair: bool) -> Vec<Issue> {
+    let tree = db.open_tree("runs").unwrap();
+    let mut issues = vec![];
+    for kv in tree.iter() {
+        let (k, v) = kv.unwrap();
+        if version(&v) > Run::VERSION {
+            issues.push(newer_version(&tree, k, &v));
+        } else if decode::<Run>(&v).is_none() {
+            if repair {
+                tree.remove(&k).unwrap();
+            }
+            issues.push(Issue::Undecodable {
+                tree: tree_name(&tree),
+                key: k,
+            });
+        }
+    }
+    issues
+}
+
+fn tree_name(tree: &Tree) -> String {
+    String::from_utf8_lossy(&tree.name()).into_owned()
+}
+
+fn newer_version(tree: &Tree, key: IVec, v: &[u8]) -> Issue {
+    Issue::NewerVersion {
+        tree: tree_name(tree),
+        key,
+        version: version(v),
+    }
+}
+
+fn bad_key(tree: &Tree, key: IVec) -> Issue {
+    Issue::BadKey {
+        tree: tree_name(tree),
+        key,
+    }
+}
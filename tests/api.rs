@@ -0,0 +1,141 @@
+//! Routes, pagination and errors of the JSON API.
+
+mod common;
+
+use common::{fixture, temp_dir};
+use datura::{
+    api,
+    config::Config,
+    download::{u32_to_ivec, Trees},
+    extract::{Book, Movie},
+    schema::encode,
+};
+use serde_json::Value;
+use std::path::Path;
+
+fn config(covers: &Path) -> Config {
+    let covers = covers.display();
+    toml::from_str(&format!(
+        "db = 'unused'\n\
+         book_cover_path = '{covers}/books'\n\
+         movie_cover_path = '{covers}/movies'\n\
+         album_cover_path = '{covers}/albums'\n"
+    ))
+    .unwrap()
+}
+
+/// Books 1, 2 and 5, the cover of 1, and movies 1 to 600, with the covers in the temp
+/// dir `name`.
+async fn start(name: &str) -> String {
+    let covers = temp_dir(name);
+    std::fs::create_dir_all(covers.join("books")).unwrap();
+    std::fs::write(covers.join("books/1.jpg"), "cover 1").unwrap();
+
+    let db = sled::Config::new().temporary(true).open().unwrap();
+    let books = Trees::open::<Book>(&db);
+    books.store(1, &fixture::<Book>("book", "full"));
+    books.store(2, &fixture::<Book>("book", "translated"));
+    books.store(5, &fixture::<Book>("book", "no_isbn"));
+    books.covers.insert(u32_to_ivec(1), vec![]).unwrap();
+    let movies = Trees::open::<Movie>(&db);
+    let movie = encode(&fixture::<Movie>("movie", "film"));
+    for id in 1..=600 {
+        movies
+            .data
+            .insert(u32_to_ivec(id), movie.as_slice())
+            .unwrap();
+    }
+
+    let (addr, _) = api::spawn(&db, &config(&covers), "127.0.0.1:0").unwrap();
+    format!("http://{addr}")
+}
+
+async fn get(url: String) -> (u16, Value) {
+    let res = reqwest::get(url).await.unwrap();
+    let status = res.status().as_u16();
+    let body = res.text().await.unwrap();
+    (status, serde_json::from_str(&body).unwrap_or(Value::Null))
+}
+
+fn ids(list: &Value) -> Vec<u64> {
+    list["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|one| one["id"].as_u64().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn items() {
+    let base = start("api-items").await;
+
+    let (status, one) = get(format!("{base}/books/2")).await;
+    assert_eq!(status, 200);
+    assert_eq!(
+        (one["id"].as_u64(), one["title"].as_str()),
+        (Some(2), Some("百年孤独"))
+    );
+    assert!(one["cover_url"].is_null());
+    let (_, one) = get(format!("{base}/books/1")).await;
+    assert_eq!(one["cover_url"], "/covers/books/1.jpg");
+    let cover = reqwest::get(format!("{base}/covers/books/1.jpg"))
+        .await
+        .unwrap();
+    assert_eq!(cover.text().await.unwrap(), "cover 1");
+
+    let (status, ratings) = get(format!("{base}/books/2/ratings")).await;
+    assert_eq!(status, 200);
+    assert_eq!(ratings.as_array().unwrap().len(), 1);
+    assert!(ratings[0]["at"].is_u64());
+
+    for path in [
+        "/books/3",
+        "/books/3/ratings",
+        "/albums/1",
+        "/covers/books/2.jpg",
+    ] {
+        assert_eq!(get(format!("{base}{path}")).await.0, 404, "{path}");
+    }
+    assert_eq!(get(format!("{base}/books/abc")).await.0, 400);
+}
+
+#[tokio::test]
+async fn pages() {
+    let base = start("api-pages").await;
+
+    let (_, list) = get(format!("{base}/books")).await;
+    assert_eq!(ids(&list), [1, 2, 5]);
+    assert!(list["next"].is_null());
+
+    let (_, list) = get(format!("{base}/books?limit=2")).await;
+    assert_eq!((ids(&list), list["next"].as_u64()), (vec![1, 2], Some(2)));
+    let (_, list) = get(format!("{base}/books?limit=2&after=2")).await;
+    assert_eq!(ids(&list), [5]);
+    assert!(list["next"].is_null());
+    let (_, list) = get(format!("{base}/books?after=5")).await;
+    assert!(ids(&list).is_empty());
+    assert!(list["next"].is_null());
+
+    // limits are clamped to 1..=500, the default is 50
+    let (_, list) = get(format!("{base}/books?limit=0")).await;
+    assert_eq!((ids(&list), list["next"].as_u64()), (vec![1], Some(1)));
+    let (_, list) = get(format!("{base}/movies")).await;
+    assert_eq!((ids(&list).len(), list["next"].as_u64()), (50, Some(50)));
+    let (_, list) = get(format!("{base}/movies?limit=100000&after=50")).await;
+    let page = ids(&list);
+    assert_eq!((page[0], page.len()), (51, 500));
+    assert_eq!(list["next"].as_u64(), Some(550));
+
+    assert_eq!(get(format!("{base}/books?limit=-1")).await.0, 400);
+}
+
+#[tokio::test]
+async fn bind_errors() {
+    let taken = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let db = sled::Config::new().temporary(true).open().unwrap();
+    let config = config(&std::env::temp_dir());
+    let addr = taken.local_addr().unwrap().to_string();
+    assert!(api::spawn(&db, &config, &addr).is_err());
+    assert!(api::spawn(&db, &config, "nowhere").is_err());
+}
@@ -0,0 +1,139 @@
+//! A local stand-in for a neodb instance, serving fixture pages and covers, and the
+//! helpers shared by the tests.
+
+// each test crate uses some of the helpers only
+#![allow(dead_code)]
+
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, Request, StatusCode},
+    response::{IntoResponse, Response},
+    Router,
+};
+use datura::download::{Site, Web};
+use std::{
+    collections::{HashMap, HashSet},
+    net::{SocketAddr, TcpListener},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+/// what the mock answers for a path
+#[derive(Clone)]
+pub enum Reply {
+    /// 200 with the body, and an `ETag` for conditional requests
+    Ok(Vec<u8>),
+    /// any other status, no body
+    Status(u16),
+}
+
+#[derive(Default)]
+struct Inner {
+    routes: HashMap<String, Reply>,
+    /// paths answering 503 until they were requested this many times
+    flaky: HashMap<String, u32>,
+    hits: HashMap<String, u32>,
+}
+
+#[derive(Clone, Default)]
+pub struct MockSite {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl MockSite {
+    /// serve the fixture `tests/fixtures/{kind}/{name}.html` at `path`
+    pub fn page(&self, path: &str, kind: &str, name: &str) -> &Self {
+        let body = std::fs::read(fixture_dir().join(kind).join(format!("{name}.html"))).unwrap();
+        self.reply(path, Reply::Ok(body))
+    }
+
+    pub fn reply(&self, path: &str, reply: Reply) -> &Self {
+        let mut inner = self.inner.lock().unwrap();
+        inner.routes.insert(path.to_owned(), reply);
+        self
+    }
+
+    /// answer 503 to the first `times` requests of `path`
+    pub fn flaky(&self, path: &str, times: u32) -> &Self {
+        let mut inner = self.inner.lock().unwrap();
+        inner.flaky.insert(path.to_owned(), times);
+        self
+    }
+
+    /// how many times `path` was requested
+    pub fn hits(&self, path: &str) -> u32 {
+        let inner = self.inner.lock().unwrap();
+        inner.hits.get(path).copied().unwrap_or_default()
+    }
+
+    fn answer(&self, path: &str, headers: &HeaderMap) -> Response {
+        let mut inner = self.inner.lock().unwrap();
+        let hits = inner.hits.entry(path.to_owned()).or_default();
+        *hits += 1;
+        let hits = *hits;
+
+        if inner.flaky.get(path).is_some_and(|times| hits <= *times) {
+            return StatusCode::SERVICE_UNAVAILABLE.into_response();
+        }
+        match inner.routes.get(path) {
+            Some(Reply::Ok(body)) => {
+                let etag = format!("\"{:x}\"", body.len() * 31 + path.len());
+                if headers
+                    .get(header::IF_NONE_MATCH)
+                    .is_some_and(|v| v.as_bytes() == etag.as_bytes())
+                {
+                    return (StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response();
+                }
+                (StatusCode::OK, [(header::ETAG, etag)], body.clone()).into_response()
+            }
+            Some(Reply::Status(code)) => StatusCode::from_u16(*code).unwrap().into_response(),
+            None => StatusCode::NOT_FOUND.into_response(),
+        }
+    }
+
+    /// Start serving on a free local port, returning the [Site] to crawl it.
+    pub fn start(&self) -> Site {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr: SocketAddr = listener.local_addr().unwrap();
+        let mock = self.clone();
+        let app = Router::new().fallback(move |req: Request<Body>| {
+            let mock = mock.clone();
+            async move { mock.answer(req.uri().path(), req.headers()) }
+        });
+        tokio::spawn(async move {
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(app.into_make_service())
+                .await
+                .unwrap();
+        });
+        Site::new(&format!("http://{addr}"))
+    }
+}
+
+pub fn fixture_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures")
+}
+
+/// the item in the fixture `kind/name.html`
+pub fn fixture<T: Web>(kind: &str, name: &str) -> T {
+    let html = std::fs::read_to_string(fixture_dir().join(kind).join(format!("{name}.html")));
+    T::try_from(html.unwrap().as_str()).ok().unwrap()
+}
+
+/// a fresh directory under the system temp dir
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("datura-{name}-{}", std::process::id()));
+    std::fs::remove_dir_all(&dir).ok();
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// ids of `outcomes` matching `pred`
+pub fn ids_where<T: Copy>(outcomes: &[(u32, T)], pred: impl Fn(T) -> bool) -> HashSet<u32> {
+    outcomes
+        .iter()
+        .filter(|(_, o)| pred(*o))
+        .map(|(id, _)| *id)
+        .collect()
+}
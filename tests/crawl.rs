@@ -0,0 +1,460 @@
+//! End-to-end crawls of books, movies and albums against the mock site in `common`.
+
+mod common;
+
+use common::{fixture, ids_where, temp_dir, MockSite, Reply};
+use datura::{
+    download::{u32_to_ivec, Outcome, Site, Trees, Web},
+    extract::{Album, Book, Movie},
+    index::lookup,
+    people::appearances,
+    ratings::history,
+    verify::{verify, Issue},
+};
+use std::collections::HashSet;
+
+/// Books 1 to 6, with 3 missing, 5 failing once with 503 and 6 not an item page.
+/// `find_newest_id` only probes 4, 6 and 7 below 8, so the gap and the flaky page do not
+/// throw it off.
+fn books() -> MockSite {
+    let mock = MockSite::default();
+    mock.page("/books/1", "book", "full")
+        .page("/books/2", "book", "translated")
+        .reply("/books/3", Reply::Status(404))
+        .page("/books/4", "book", "no_isbn")
+        .page("/books/5", "book", "no_cover")
+        .flaky("/books/5", 1)
+        .page("/books/6", "book", "not_an_item")
+        .reply(
+            "/media/book/2022/08/9a0c3f.jpg",
+            Reply::Ok(b"cover 1".to_vec()),
+        )
+        .reply(
+            "/media/book/2022/09/1b2e4d.jpg",
+            Reply::Ok(b"cover 2".to_vec()),
+        )
+        .flaky("/media/book/2022/09/1b2e4d.jpg", 1)
+        .reply("/media/book/2022/10/5c7a11.jpg", Reply::Ok(vec![0; 4096]));
+    mock
+}
+
+/// Movies 1 to 4, with 2 missing. `find_newest_id` probes 3 and 4, not 2.
+fn movies() -> MockSite {
+    let mock = MockSite::default();
+    mock.page("/movies/1", "movie", "film")
+        .reply("/movies/2", Reply::Status(404))
+        .page("/movies/3", "movie", "series")
+        .page("/movies/4", "movie", "new_layout")
+        .reply(
+            "/media/movie/2022/08/3f8e2a.jpg",
+            Reply::Ok(b"cover 1".to_vec()),
+        )
+        .reply("/media/movie/2023/05/7d1c90.jpg", Reply::Status(404))
+        .reply(
+            "/m/item/doubanmovie/2023/04/01/9e2b6a.jpg",
+            Reply::Ok(b"cover 4".to_vec()),
+        );
+    mock
+}
+
+/// Albums 1 to 4, with 2 failing once with 503.
+fn albums() -> MockSite {
+    let mock = MockSite::default();
+    mock.page("/music/album/1", "album", "full")
+        .page("/music/album/2", "album", "discs")
+        .flaky("/music/album/2", 1)
+        .page("/music/album/3", "album", "no_tracks")
+        .page("/music/album/4", "album", "new_layout")
+        .reply(
+            "/media/album/2022/08/c41b07.jpg",
+            Reply::Ok(b"cover 1".to_vec()),
+        )
+        .reply(
+            "/media/album/2022/08/a9d3e4.jpg",
+            Reply::Ok(b"cover 2".to_vec()),
+        )
+        .reply(
+            "/media/album/2022/09/0e55d2.jpg",
+            Reply::Ok(b"cover 3".to_vec()),
+        )
+        .reply(
+            "/m/item/spotify_album/2023/02/20/c3f1a9.jpg",
+            Reply::Ok(b"cover 4".to_vec()),
+        );
+    mock
+}
+
+async fn get_all<T: Web>(site: &Site, trees: &Trees, ids: Vec<u32>) -> Vec<(u32, Outcome)> {
+    let mut outcomes = vec![];
+    for id in ids {
+        outcomes.push((id, T::get_data(site, id, trees).await));
+    }
+    outcomes
+}
+
+async fn dl_all<T: Web>(
+    site: &Site,
+    trees: &Trees,
+    ids: Vec<u32>,
+    cover_path: &str,
+    max_size: u64,
+) -> Vec<(u32, Outcome)> {
+    let mut outcomes = vec![];
+    for id in ids {
+        outcomes.push((id, T::dl_cover(site, id, trees, cover_path, max_size).await));
+    }
+    outcomes
+}
+
+#[tokio::test]
+async fn crawl_books() {
+    let mock = books();
+    let site = mock.start();
+    let dir = temp_dir("crawl");
+    let db = sled::Config::new().temporary(true).open().unwrap();
+    let trees = Trees::open::<Book>(&db);
+    let cover_path = dir.to_str().unwrap();
+
+    // first pass
+    let ids = Book::check_ids(&trees.data, &trees.not_found, &site).await;
+    assert_eq!(ids, vec![1, 2, 3, 4, 5, 6]);
+    let outcomes = get_all::<Book>(&site, &trees, ids).await;
+    assert_eq!(
+        ids_where(&outcomes, |o| matches!(o, Outcome::Stored(_))),
+        HashSet::from([1, 2, 4])
+    );
+    assert_eq!(
+        ids_where(&outcomes, |o| o == Outcome::NotFound),
+        HashSet::from([3])
+    );
+    assert_eq!(
+        ids_where(&outcomes, |o| o == Outcome::Failed),
+        HashSet::from([5])
+    );
+    assert_eq!(
+        ids_where(&outcomes, |o| o == Outcome::ParseError),
+        HashSet::from([6])
+    );
+    assert!(trees.not_found.contains_key(u32_to_ivec(3)).unwrap());
+    // the page failing to parse is kept, the others are not
+    assert!(trees.pages.contains_key(u32_to_ivec(6)).unwrap());
+    assert!(!trees.pages.contains_key(u32_to_ivec(1)).unwrap());
+
+    // second pass retries what failed, but not the 404
+    let ids = Book::check_ids(&trees.data, &trees.not_found, &site).await;
+    assert_eq!(ids, vec![5, 6]);
+    let outcomes = get_all::<Book>(&site, &trees, ids).await;
+    assert_eq!(
+        ids_where(&outcomes, |o| matches!(o, Outcome::Stored(_))),
+        HashSet::from([5])
+    );
+    assert_eq!(mock.hits("/books/3"), 1);
+
+    let found = lookup(&db, "isbn", "7-5442-5399-6");
+    assert_eq!(found.len(), 1);
+    assert_eq!((found[0].id, found[0].title.as_str()), (2, "百年孤独"));
+
+    let found = appearances(&db, "范晔", None);
+    assert_eq!(found.len(), 1);
+    assert_eq!(
+        (found[0].id, found[0].title.as_deref()),
+        (2, Some("百年孤独"))
+    );
+    assert_eq!(found[0].roles, vec!["translator"]);
+
+    // ratings are recorded, but not the empty one of 5
+    let ratings = history(&trees.ratings, 1);
+    assert_eq!(ratings.len(), 1);
+    assert_eq!(
+        (ratings[0].1.score, ratings[0].1.count, ratings[0].1.marks),
+        (Some(8.8), Some(1432), Some(2817))
+    );
+    assert!(history(&trees.ratings, 5).is_empty());
+
+    // covers: 5 has none, 2 fails once, 4 is larger than allowed
+    let ids = Book::check_ids(&trees.covers, &trees.not_found, &site).await;
+    assert_eq!(ids, vec![1, 2, 4, 5, 6]);
+    let outcomes = dl_all::<Book>(&site, &trees, ids, cover_path, 1024).await;
+    assert_eq!(
+        ids_where(&outcomes, |o| matches!(o, Outcome::Stored(_))),
+        HashSet::from([1])
+    );
+    assert_eq!(
+        ids_where(&outcomes, |o| o == Outcome::Failed),
+        HashSet::from([2])
+    );
+    assert_eq!(
+        ids_where(&outcomes, |o| o == Outcome::Skipped),
+        HashSet::from([4, 5, 6])
+    );
+    assert_eq!(std::fs::read(dir.join("1.jpg")).unwrap(), b"cover 1");
+    assert!(!dir.join("4.jpg").exists());
+    assert!(!dir.join("4.jpg.part").exists());
+
+    // the cover too large is not fetched again under the same limit
+    let cover_4 = "/media/book/2022/10/5c7a11.jpg";
+    assert_eq!(mock.hits(cover_4), 1);
+    let outcome = Book::dl_cover(&site, 4, &trees, cover_path, 1024).await;
+    assert_eq!(outcome, Outcome::Skipped);
+    assert_eq!(mock.hits(cover_4), 1);
+
+    let ids = Book::check_ids(&trees.covers, &trees.not_found, &site).await;
+    let outcomes = dl_all::<Book>(&site, &trees, ids, cover_path, 8192).await;
+    assert_eq!(
+        ids_where(&outcomes, |o| matches!(o, Outcome::Stored(_))),
+        HashSet::from([2, 4])
+    );
+    assert_eq!(std::fs::read(dir.join("2.jpg")).unwrap(), b"cover 2");
+    assert!(!trees.cover_too_large.contains_key(u32_to_ivec(4)).unwrap());
+
+    // refresh: everything is unchanged, answered with 304
+    let outcomes = get_all::<Book>(&site, &trees, Book::stored_ids(&trees.data)).await;
+    assert!(outcomes.iter().all(|(_, o)| *o == Outcome::Unchanged));
+    let outcomes = dl_all::<Book>(
+        &site,
+        &trees,
+        Book::stored_ids(&trees.covers),
+        cover_path,
+        8192,
+    )
+    .await;
+    assert!(outcomes.iter().all(|(_, o)| *o == Outcome::Unchanged));
+    assert_eq!(history(&trees.ratings, 1).len(), 1);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn crawl_movies() {
+    let mock = movies();
+    let site = mock.start();
+    let dir = temp_dir("crawl-movies");
+    let db = sled::Config::new().temporary(true).open().unwrap();
+    let trees = Trees::open::<Movie>(&db);
+    let cover_path = dir.to_str().unwrap();
+
+    let ids = Movie::check_ids(&trees.data, &trees.not_found, &site).await;
+    assert_eq!(ids, vec![1, 2, 3, 4]);
+    let outcomes = get_all::<Movie>(&site, &trees, ids).await;
+    assert_eq!(
+        ids_where(&outcomes, |o| matches!(o, Outcome::Stored(_))),
+        HashSet::from([1, 3, 4])
+    );
+    assert_eq!(
+        ids_where(&outcomes, |o| o == Outcome::NotFound),
+        HashSet::from([2])
+    );
+    assert!(Movie::check_ids(&trees.data, &trees.not_found, &site)
+        .await
+        .is_empty());
+
+    let found = lookup(&db, "imdb", "tt27239411");
+    assert_eq!((found[0].id, found[0].title.as_str()), (3, "漫长的季节"));
+    assert_eq!(
+        appearances(&db, "姜文", None)[0].roles,
+        vec!["director", "writer", "actor"]
+    );
+
+    // covers: the one of 3 is gone from the site
+    let ids = Movie::check_ids(&trees.covers, &trees.not_found, &site).await;
+    assert_eq!(ids, vec![1, 3, 4]);
+    let outcomes = dl_all::<Movie>(&site, &trees, ids, cover_path, 8192).await;
+    assert_eq!(
+        ids_where(&outcomes, |o| matches!(o, Outcome::Stored(_))),
+        HashSet::from([1, 4])
+    );
+    assert_eq!(
+        ids_where(&outcomes, |o| o == Outcome::NotFound),
+        HashSet::from([3])
+    );
+    assert_eq!(std::fs::read(dir.join("4.jpg")).unwrap(), b"cover 4");
+
+    let outcomes = get_all::<Movie>(&site, &trees, Movie::stored_ids(&trees.data)).await;
+    assert!(outcomes.iter().all(|(_, o)| *o == Outcome::Unchanged));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn crawl_albums() {
+    let mock = albums();
+    let site = mock.start();
+    let dir = temp_dir("crawl-albums");
+    let db = sled::Config::new().temporary(true).open().unwrap();
+    let trees = Trees::open::<Album>(&db);
+    let cover_path = dir.to_str().unwrap();
+
+    let ids = Album::check_ids(&trees.data, &trees.not_found, &site).await;
+    assert_eq!(ids, vec![1, 2, 3, 4]);
+    let outcomes = get_all::<Album>(&site, &trees, ids).await;
+    assert_eq!(
+        ids_where(&outcomes, |o| matches!(o, Outcome::Stored(_))),
+        HashSet::from([1, 3, 4])
+    );
+    assert_eq!(
+        ids_where(&outcomes, |o| o == Outcome::Failed),
+        HashSet::from([2])
+    );
+
+    // the album failing is retried
+    let ids = Album::check_ids(&trees.data, &trees.not_found, &site).await;
+    assert_eq!(ids, vec![2]);
+    let outcomes = get_all::<Album>(&site, &trees, ids).await;
+    assert!(matches!(outcomes[..], [(2, Outcome::Stored(_))]));
+    let found = lookup(&db, "barcode", "077774604329");
+    assert_eq!((found[0].id, found[0].title.as_str()), (2, "The Wall"));
+
+    let found = lookup(&db, "barcode", "724385522925");
+    assert_eq!((found[0].id, found[0].title.as_str()), (1, "OK Computer"));
+    assert_eq!(appearances(&db, "Radiohead", None)[0].roles, vec!["artist"]);
+
+    let ids = Album::check_ids(&trees.covers, &trees.not_found, &site).await;
+    let outcomes = dl_all::<Album>(&site, &trees, ids, cover_path, 8192).await;
+    assert_eq!(
+        ids_where(&outcomes, |o| matches!(o, Outcome::Stored(_))),
+        HashSet::from([1, 2, 3, 4])
+    );
+    assert_eq!(std::fs::read(dir.join("2.jpg")).unwrap(), b"cover 2");
+
+    let outcomes = dl_all::<Album>(
+        &site,
+        &trees,
+        Album::stored_ids(&trees.covers),
+        cover_path,
+        8192,
+    )
+    .await;
+    assert!(outcomes.iter().all(|(_, o)| *o == Outcome::Unchanged));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn server_errors_are_not_404() {
+    let mock = MockSite::default();
+    mock.reply("/books/2", Reply::Status(500));
+    let site = mock.start();
+    let db = sled::Config::new().temporary(true).open().unwrap();
+    let trees = Trees::open::<Book>(&db);
+
+    assert_eq!(Book::get_data(&site, 2, &trees).await, Outcome::Failed);
+    assert!(!trees.not_found.contains_key(u32_to_ivec(2)).unwrap());
+    assert_eq!(Book::get_data(&site, 9, &trees).await, Outcome::NotFound);
+    assert!(trees.not_found.contains_key(u32_to_ivec(9)).unwrap());
+}
+
+#[tokio::test]
+async fn unreachable_sites_skip_discovery() {
+    // a port nothing listens on any more
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let site = Site::new(&format!("http://{}", listener.local_addr().unwrap()));
+    drop(listener);
+    let db = sled::Config::new().temporary(true).open().unwrap();
+    let trees = Trees::open::<Book>(&db);
+
+    assert!(Book::check_ids(&trees.data, &trees.not_found, &site)
+        .await
+        .is_empty());
+    assert!(Book::check_new_ids(&trees.data, &trees.not_found, &site)
+        .await
+        .is_empty());
+}
+
+#[tokio::test]
+async fn pages_are_kept_until_they_parse() {
+    let mock = MockSite::default();
+    mock.page("/books/1", "book", "not_an_item");
+    let site = mock.start();
+    let db = sled::Config::new().temporary(true).open().unwrap();
+    let mut trees = Trees::open::<Book>(&db);
+
+    assert_eq!(Book::get_data(&site, 1, &trees).await, Outcome::ParseError);
+    assert!(trees.pages.contains_key(u32_to_ivec(1)).unwrap());
+    // fixed on the site, the page is dropped
+    mock.page("/books/1", "book", "full");
+    assert!(matches!(
+        Book::get_data(&site, 1, &trees).await,
+        Outcome::Stored(_)
+    ));
+    assert!(trees.pages.is_empty());
+
+    trees.keep_pages = true;
+    mock.page("/books/1", "book", "markup");
+    assert!(matches!(
+        Book::get_data(&site, 1, &trees).await,
+        Outcome::Stored(_)
+    ));
+    assert!(trees.pages.contains_key(u32_to_ivec(1)).unwrap());
+}
+
+#[tokio::test]
+async fn people_and_sources_follow_the_stored_item() {
+    let mock = MockSite::default();
+    mock.page("/books/1", "book", "new_layout");
+    let site = mock.start();
+    let db = sled::Config::new().temporary(true).open().unwrap();
+    let trees = Trees::open::<Book>(&db);
+
+    assert!(matches!(
+        Book::get_data(&site, 1, &trees).await,
+        Outcome::Stored(_)
+    ));
+    let found = appearances(&db, "[美] 丹尼尔·凯斯", None);
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].url.as_deref(), Some("/people/5d2e81/"));
+    assert_eq!(found[0].roles, vec!["author"]);
+    // every source is indexed, not only the first
+    let goodreads = "https://www.goodreads.com/book/show/18373.Flowers_for_Algernon";
+    assert_eq!(lookup(&db, "source", goodreads).len(), 1);
+
+    // the page changed, the former people and sources are dropped
+    mock.page("/books/1", "book", "markup");
+    assert!(matches!(
+        Book::get_data(&site, 1, &trees).await,
+        Outcome::Stored(_)
+    ));
+    assert!(appearances(&db, "[美] 丹尼尔·凯斯", None).is_empty());
+    assert!(lookup(&db, "source", goodreads).is_empty());
+    let found = appearances(&db, "J.K. Rowling", Some("/people/3f9a1c/"));
+    assert_eq!((found[0].kind.as_str(), found[0].id), ("book", 1));
+    assert!(appearances(&db, "J.K. Rowling", Some("/people/other/")).is_empty());
+
+    // rebuilt the same by reindex
+    assert_eq!(trees.reindex::<Book>(), 1);
+    assert_eq!(appearances(&db, "Tom \"T\" Smith", None).len(), 1);
+}
+
+#[test]
+fn items_sharing_an_index_key() {
+    let db = sled::Config::new().temporary(true).open().unwrap();
+    let ids = |found: Vec<datura::index::Found>| found.iter().map(|f| f.id).collect::<Vec<_>>();
+
+    let books = Trees::open::<Book>(&db);
+    for (id, name) in [(1, "full"), (2, "markup"), (3, "translated")] {
+        books.store(id, &fixture::<Book>("book", name));
+    }
+    assert_eq!(ids(lookup(&db, "isbn", "978-7-5366-9238-1")), [1, 2]);
+    // 2 lost its isbn, 1 keeps it
+    books.store(2, &fixture::<Book>("book", "no_isbn"));
+    assert_eq!(ids(lookup(&db, "isbn", "9787536692381")), [1]);
+
+    // barcodes are normalised like isbns, a UPC-A as an EAN-13
+    let albums = Trees::open::<Album>(&db);
+    albums.store(1, &fixture::<Album>("album", "full"));
+    albums.store(2, &fixture::<Album>("album", "english"));
+    assert_eq!(ids(lookup(&db, "barcode", "7 24385 52292 5")), [1, 2]);
+    assert_eq!(ids(lookup(&db, "barcode", "0724385522925")), [1, 2]);
+    assert!(lookup(&db, "barcode", "724385522926").is_empty());
+
+    // an entry of before the keys were shared is repaired by rebuilding the index
+    let isbn = &books.indexes[0];
+    isbn.insert("9787544253994", u32_to_ivec(3)).unwrap();
+    let dir = temp_dir("shared-keys");
+    let issues = verify::<Book>(&db, dir.to_str().unwrap(), true);
+    assert!(
+        matches!(issues.as_slice(), [Issue::BadKey { .. }]),
+        "{issues:?}"
+    );
+    assert_eq!(isbn.len(), 2);
+    assert_eq!(ids(lookup(&db, "isbn", "9787544253994")), [3]);
+}
@@ -0,0 +1,94 @@
+//! Golden tests of the extractors over the saved pages in `tests/fixtures/{kind}`.
+//!
+//! Each `name.html` is extracted and compared with `name.json`, an item or
+//! `{"error": ...}`. Run with `UPDATE_GOLDEN=1` to write the json files after a change,
+//! and review their diff. New pages are captured with `neodb <config.toml> capture`.
+
+use datura::extract::{Album, Book, Movie, ParseError};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{fs, path::Path};
+
+fn extract<T>(html: &str) -> Value
+where
+    T: Serialize + for<'a> TryFrom<&'a str, Error = ParseError>,
+{
+    match T::try_from(html) {
+        Ok(one) => serde_json::to_value(one).unwrap(),
+        Err(e) => json!({ "error": e.to_string() }),
+    }
+}
+
+fn check<T>(kind: &str)
+where
+    T: Serialize + for<'a> TryFrom<&'a str, Error = ParseError>,
+{
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(kind);
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let mut failed = vec![];
+    let mut cnt = 0;
+
+    for entry in fs::read_dir(&dir).unwrap() {
+        let html_path = entry.unwrap().path();
+        if html_path.extension().is_none_or(|ext| ext != "html") {
+            continue;
+        }
+        cnt += 1;
+        let got = extract::<T>(&fs::read_to_string(&html_path).unwrap());
+        let golden_path = html_path.with_extension("json");
+        if update {
+            let pretty = serde_json::to_string_pretty(&got).unwrap();
+            fs::write(&golden_path, pretty + "\n").unwrap();
+            continue;
+        }
+
+        let expected: Value = match fs::read_to_string(&golden_path) {
+            Ok(s) => serde_json::from_str(&s).unwrap(),
+            Err(_) => {
+                failed.push(format!("{}: no golden file", golden_path.display()));
+                continue;
+            }
+        };
+        if got != expected {
+            failed.push(format!(
+                "{}:\nexpected {}\ngot      {}",
+                html_path.display(),
+                expected,
+                got
+            ));
+        }
+    }
+
+    assert!(cnt > 0, "no fixtures in {}", dir.display());
+    assert!(failed.is_empty(), "\n{}", failed.join("\n\n"));
+}
+
+#[test]
+fn books() {
+    check::<Book>("book");
+}
+
+/// links in the marks and reviews headers of the old layout are not sources
+#[test]
+fn section_links_are_not_sources() {
+    let html = fs::read_to_string(
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/book/full.html"),
+    )
+    .unwrap();
+    assert!(html.contains("https://neodb.social/book/2567698/marks"));
+    let book = Book::try_from(html.as_str()).ok().unwrap();
+    let urls: Vec<&str> = book.sources.iter().map(|s| s.url.as_str()).collect();
+    assert_eq!(urls, ["https://book.douban.com/subject/2567698/"]);
+}
+
+#[test]
+fn movies() {
+    check::<Movie>("movie");
+}
+
+#[test]
+fn albums() {
+    check::<Album>("album");
+}
@@ -0,0 +1,84 @@
+//! ISBN checksums and conversion to ISBN-13, and barcodes to EAN-13.
+
+use datura::isbn::{is_valid_isbn10, is_valid_isbn13, normalize_barcode, to_ean13, to_isbn13};
+
+#[test]
+fn checksums() {
+    assert!(is_valid_isbn13("9780306406157"));
+    assert!(is_valid_isbn13("9787020002207"));
+    assert!(!is_valid_isbn13("9780306406158"));
+    // not a bookland prefix
+    assert!(!is_valid_isbn13("1234567890128"));
+    assert!(!is_valid_isbn13("978030640615"));
+
+    assert!(is_valid_isbn10("0306406152"));
+    assert!(is_valid_isbn10("7539921390"));
+    assert!(!is_valid_isbn10("0306406153"));
+    assert!(!is_valid_isbn10("030640615"));
+}
+
+#[test]
+fn x_check_digit() {
+    assert!(is_valid_isbn10("080442957X"));
+    assert!(is_valid_isbn10("080442957x"));
+    assert!(is_valid_isbn10("702000220X"));
+    assert!(!is_valid_isbn10("0306406150"));
+    assert!(!is_valid_isbn10("030640615X"));
+    // only the check digit may be X
+    assert!(!is_valid_isbn10("08044295X7"));
+}
+
+#[test]
+fn conversion() {
+    for (s, expected) in [
+        ("9780306406157", "9780306406157"),
+        ("978-0-306-40615-7", "9780306406157"),
+        ("0306406152", "9780306406157"),
+        ("0-306-40615-2", "9780306406157"),
+        ("ISBN 0-306-40615-2", "9780306406157"),
+        ("isbn: 978 0 306 40615 7", "9780306406157"),
+        ("ISBN：080442957X", "9780804429573"),
+        ("7-02-000220-x", "9787020002207"),
+        (" 7539921390 ", "9787539921396"),
+    ] {
+        assert_eq!(to_isbn13(s).as_deref(), Some(expected), "{s}");
+    }
+}
+
+#[test]
+fn invalid() {
+    for s in [
+        "",
+        "ISBN",
+        "9780306406158",
+        "0306406153",
+        "03064061",
+        "97803064061577",
+        "978030640615X",
+        "abcdefghij",
+        "SH10019-2345",
+        "统一书号 10019·2345",
+    ] {
+        assert_eq!(to_isbn13(s), None, "{s}");
+    }
+}
+
+#[test]
+fn barcodes() {
+    for (s, expected) in [
+        ("4988005123456", None),
+        ("4988005123459", Some("4988005123459")),
+        ("724385522925", Some("0724385522925")),
+        ("7 24385 52292 5", Some("0724385522925")),
+        ("96385074", Some("0000096385074")),
+        ("724385522926", None),
+        ("9780306406157", Some("9780306406157")),
+        ("SICX-30001", None),
+        ("", None),
+    ] {
+        assert_eq!(to_ean13(s).as_deref(), expected, "{s}");
+    }
+    assert_eq!(normalize_barcode(" 724385-522925"), "0724385522925");
+    assert_eq!(normalize_barcode("sicx-30001"), "SICX30001");
+    assert_eq!(normalize_barcode("077774604329"), "077774604329");
+}
@@ -0,0 +1,114 @@
+//! Prometheus exposition of [Metrics].
+
+use datura::{download::Outcome, metrics::Metrics};
+use std::{sync::Arc, time::Duration};
+use tokio::sync::Semaphore;
+
+fn lines(out: &str, prefix: &str) -> Vec<String> {
+    out.lines()
+        .filter(|l| l.starts_with(prefix))
+        .map(|l| l.to_owned())
+        .collect()
+}
+
+#[test]
+fn empty() {
+    let out = Metrics::default().render();
+    for name in [
+        "datura_requests_total counter",
+        "datura_fetch_duration_seconds histogram",
+        "datura_parse_failures_total counter",
+        "datura_downloaded_bytes_total counter",
+        "datura_semaphore_in_use gauge",
+        "datura_semaphore_permits gauge",
+    ] {
+        assert!(out.contains(&format!("# TYPE {name}\n")), "{name}");
+    }
+    assert!(out.lines().all(|l| l.starts_with('#')));
+}
+
+#[test]
+fn requests_and_outcomes() {
+    let metrics = Metrics::default();
+    metrics.request("book", Some(200), Duration::from_millis(80));
+    metrics.request("book", Some(200), Duration::from_secs(3));
+    metrics.request("book", Some(404), Duration::from_millis(10));
+    metrics.request("movie_cover", None, Duration::from_secs(60));
+    metrics.outcome("book", &Outcome::Stored(100));
+    metrics.outcome("book", &Outcome::Stored(20));
+    metrics.outcome("book", &Outcome::ParseError);
+    metrics.outcome("book", &Outcome::NotFound);
+    let out = metrics.render();
+
+    assert_eq!(
+        lines(&out, "datura_requests_total"),
+        [
+            "datura_requests_total{kind=\"book\",status=\"200\"} 2",
+            "datura_requests_total{kind=\"book\",status=\"404\"} 1",
+            "datura_requests_total{kind=\"movie_cover\",status=\"error\"} 1",
+        ]
+    );
+    let book: Vec<String> = lines(&out, "datura_fetch_duration_seconds_bucket{kind=\"book\"");
+    assert_eq!(book.len(), 10);
+    assert_eq!(
+        book[0],
+        "datura_fetch_duration_seconds_bucket{kind=\"book\",le=\"0.05\"} 1"
+    );
+    assert_eq!(
+        book[1],
+        "datura_fetch_duration_seconds_bucket{kind=\"book\",le=\"0.1\"} 2"
+    );
+    assert_eq!(
+        book[6],
+        "datura_fetch_duration_seconds_bucket{kind=\"book\",le=\"5\"} 3"
+    );
+    assert_eq!(
+        book[9],
+        "datura_fetch_duration_seconds_bucket{kind=\"book\",le=\"+Inf\"} 3"
+    );
+    // slower than the last bucket
+    assert!(
+        out.contains("datura_fetch_duration_seconds_bucket{kind=\"movie_cover\",le=\"30\"} 0\n")
+    );
+    assert!(
+        out.contains("datura_fetch_duration_seconds_bucket{kind=\"movie_cover\",le=\"+Inf\"} 1\n")
+    );
+    assert!(out.contains("datura_fetch_duration_seconds_count{kind=\"book\"} 3\n"));
+    assert!(out.contains("datura_fetch_duration_seconds_sum{kind=\"movie_cover\"} 60\n"));
+
+    assert_eq!(
+        lines(&out, "datura_parse_failures_total{"),
+        ["datura_parse_failures_total{kind=\"book\"} 1"]
+    );
+    assert_eq!(
+        lines(&out, "datura_downloaded_bytes_total{"),
+        ["datura_downloaded_bytes_total{kind=\"book\"} 120"]
+    );
+}
+
+#[test]
+fn semaphores() {
+    let metrics = Metrics::default();
+    let semaphore = Arc::new(Semaphore::new(4));
+    metrics.watch_semaphore("pages", &semaphore, 4);
+    let _permits = semaphore.try_acquire_many(3).unwrap();
+    let out = metrics.render();
+    assert!(out.contains("datura_semaphore_in_use{pool=\"pages\"} 3\n"));
+    assert!(out.contains("datura_semaphore_permits{pool=\"pages\"} 4\n"));
+}
+
+#[tokio::test]
+async fn serve() {
+    let addr = datura::metrics::serve("127.0.0.1:0").unwrap();
+    let out = reqwest::get(format!("http://{addr}/metrics"))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(out.contains("# TYPE datura_requests_total counter\n"));
+
+    let taken = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    assert!(datura::metrics::serve(&taken.local_addr().unwrap().to_string()).is_err());
+    assert!(datura::metrics::serve("not an address").is_err());
+}
@@ -0,0 +1,241 @@
+//! Manifests and download state of `covers`.
+
+use datura::mirror::{by_dest_name, claim_names, read_manifest, Entry, State, MAX_ATTEMPTS};
+use sled::Tree;
+use std::{fs, path::PathBuf};
+
+/// `content` written to a fresh file `name` under the system temp dir
+fn temp_file(name: &str, content: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("datura-mirror-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    fs::write(&path, content).unwrap();
+    path
+}
+
+fn entry(url: &str, dest_name: Option<&str>) -> Entry {
+    Entry {
+        url: url.to_owned(),
+        dest_name: dest_name.map(|s| s.to_owned()),
+        sha256: None,
+        md5: None,
+        size: None,
+    }
+}
+
+/// `--max-size=` of the runs
+const MAX_SIZE: u64 = 1024;
+
+fn state_tree() -> Tree {
+    let db = sled::Config::new().temporary(true).open().unwrap();
+    db.open_tree("state").unwrap()
+}
+
+#[test]
+fn retryable_failures_are_given_up() {
+    let tree = state_tree();
+    for n in 1..=MAX_ATTEMPTS {
+        assert!(State::wanted(&tree, "1.jpg", false, MAX_SIZE));
+        if n.is_multiple_of(2) {
+            State::retry(&tree, "1.jpg");
+        } else {
+            State::failed(&tree, "1.jpg", 503);
+        }
+        assert!(matches!(State::get(&tree, "1.jpg"), Some(State::Retry(m)) if m == n));
+    }
+    assert!(!State::wanted(&tree, "1.jpg", false, MAX_SIZE));
+}
+
+#[test]
+fn client_errors_are_permanent() {
+    let tree = state_tree();
+    for (fname, status) in [("a.jpg", 404), ("b.jpg", 410), ("c.jpg", 403)] {
+        State::failed(&tree, fname, status);
+        assert!(matches!(State::get(&tree, fname), Some(State::Failed(s)) if s == status));
+        assert!(!State::wanted(&tree, fname, false, MAX_SIZE));
+    }
+    // timeouts and rate limiting are retried
+    for (fname, status) in [("d.jpg", 408), ("e.jpg", 429), ("f.jpg", 500)] {
+        State::failed(&tree, fname, status);
+        assert!(matches!(State::get(&tree, fname), Some(State::Retry(1))));
+        assert!(State::wanted(&tree, fname, false, MAX_SIZE));
+    }
+}
+
+#[test]
+fn files_on_disk() {
+    let tree = state_tree();
+    // downloaded before the state db
+    assert!(!State::wanted(&tree, "old.jpg", true, MAX_SIZE));
+    assert!(matches!(State::get(&tree, "old.jpg"), Some(State::Done)));
+
+    State::Done.set(&tree, "done.jpg");
+    assert!(!State::wanted(&tree, "done.jpg", true, MAX_SIZE));
+    // deleted since
+    assert!(State::wanted(&tree, "done.jpg", false, MAX_SIZE));
+
+    assert!(State::wanted(&tree, "new.jpg", false, MAX_SIZE));
+}
+
+#[test]
+fn too_large_files_until_the_max_size_is_raised() {
+    let tree = state_tree();
+    State::TooLarge(MAX_SIZE).set(&tree, "big.jpg");
+    assert!(!State::wanted(&tree, "big.jpg", false, MAX_SIZE));
+    assert!(!State::wanted(&tree, "big.jpg", false, MAX_SIZE / 2));
+    assert!(State::wanted(&tree, "big.jpg", false, MAX_SIZE * 2));
+    assert!(matches!(
+        State::get(&tree, "big.jpg"),
+        Some(State::TooLarge(MAX_SIZE))
+    ));
+}
+
+#[test]
+fn undecodable_states_are_downloaded_again() {
+    let tree = state_tree();
+    tree.insert("1.jpg", &[0xff, 0xff]).unwrap();
+    assert!(State::get(&tree, "1.jpg").is_none());
+    assert!(State::wanted(&tree, "1.jpg", false, MAX_SIZE));
+}
+
+#[test]
+fn manifests() {
+    let path = temp_file(
+        "list.txt",
+        "https://example.org/a/1.jpg\n\n  https://example.org/b/2.jpg  \n",
+    );
+    let entries = read_manifest(path.to_str().unwrap()).unwrap();
+    let urls: Vec<&str> = entries.iter().map(|e| e.url.as_str()).collect();
+    assert_eq!(
+        urls,
+        ["https://example.org/a/1.jpg", "https://example.org/b/2.jpg"]
+    );
+    assert_eq!(entries[0].dest_name(), Some("1.jpg"));
+
+    let path = temp_file(
+        "list.csv",
+        "url,dest_name,sha256,md5,size\nhttps://example.org/1.jpg,one.jpg,,,7\nhttps://example.org/2.jpg,,,,\n",
+    );
+    let entries = read_manifest(path.to_str().unwrap()).unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(
+        (entries[0].dest_name(), entries[0].size),
+        (Some("one.jpg"), Some(7))
+    );
+    assert_eq!(
+        (entries[1].dest_name(), entries[1].size),
+        (Some("2.jpg"), None)
+    );
+
+    let path = temp_file(
+        "list.jsonl",
+        "{\"url\": \"https://example.org/1.jpg\", \"md5\": \"abc\"}\n\n{\"url\": \"https://example.org/2.jpg\"}\n",
+    );
+    let entries = read_manifest(path.to_str().unwrap()).unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].md5.as_deref(), Some("abc"));
+}
+
+#[test]
+fn malformed_manifests_tell_the_line() {
+    let path = temp_file(
+        "bad.jsonl",
+        "{\"url\": \"https://example.org/1.jpg\"}\n\n{\"url\": 3}\n",
+    );
+    let err = read_manifest(path.to_str().unwrap()).unwrap_err();
+    assert!(err.contains("bad.jsonl:3:"), "{err}");
+
+    let path = temp_file(
+        "bad.csv",
+        "url,size\nhttps://example.org/1.jpg,7\nhttps://example.org/2.jpg,big\n",
+    );
+    let err = read_manifest(path.to_str().unwrap()).unwrap_err();
+    assert!(err.contains("line: 3"), "{err}");
+
+    assert!(read_manifest("/nonexistent/list.txt").is_err());
+}
+
+#[test]
+fn names() {
+    let entries = by_dest_name(vec![
+        entry("https://example.org/a/1.jpg", None),
+        // same url again, merged
+        entry("https://example.org/a/1.jpg", None),
+        // same name, other url: a collision, neither is kept
+        entry("https://example.org/a/2.jpg", Some("two.jpg")),
+        entry("https://example.org/b/2.jpg", Some("two.jpg")),
+        // not a plain file name
+        entry("https://example.org/a/3.jpg", Some("../3.jpg")),
+        entry("https://example.org/a/", None),
+    ]);
+    let mut names: Vec<&str> = entries.keys().map(|k| k.as_str()).collect();
+    names.sort();
+    assert_eq!(names, ["1.jpg"]);
+}
+
+#[test]
+fn names_taken_in_earlier_runs() {
+    let db = sled::Config::new().temporary(true).open().unwrap();
+    let urls = db.open_tree("urls").unwrap();
+    let state = db.open_tree("state").unwrap();
+
+    let mut entries = by_dest_name(vec![entry("https://example.org/a/1.jpg", None)]);
+    claim_names(&mut entries, &urls, &state);
+    assert_eq!(entries.len(), 1);
+    State::Done.set(&state, "1.jpg");
+
+    let mut entries = by_dest_name(vec![
+        entry("https://example.org/a/1.jpg", None),
+        entry("https://example.org/b/1.jpg", Some("other.jpg")),
+    ]);
+    claim_names(&mut entries, &urls, &state);
+    assert_eq!(entries.len(), 2);
+
+    let mut entries = by_dest_name(vec![entry("https://example.org/b/1.jpg", None)]);
+    claim_names(&mut entries, &urls, &state);
+    assert!(entries.is_empty());
+}
+
+#[test]
+fn names_not_downloaded_can_be_claimed_again() {
+    let db = sled::Config::new().temporary(true).open().unwrap();
+    let urls = db.open_tree("urls").unwrap();
+    let state = db.open_tree("state").unwrap();
+
+    // a mistyped url, failed for good
+    let mut entries = by_dest_name(vec![entry("https://example.org/a/1.jgp", Some("1.jpg"))]);
+    claim_names(&mut entries, &urls, &state);
+    State::failed(&state, "1.jpg", 404);
+
+    // corrected in the manifest
+    let mut entries = by_dest_name(vec![entry("https://example.org/a/1.jpg", None)]);
+    claim_names(&mut entries, &urls, &state);
+    assert_eq!(entries.len(), 1);
+    assert!(State::get(&state, "1.jpg").is_none());
+    assert_eq!(
+        urls.get("1.jpg").unwrap().as_deref(),
+        Some(&b"https://example.org/a/1.jpg"[..])
+    );
+}
+
+#[test]
+fn checksums() {
+    let path = temp_file("checked.jpg", "cover 1");
+    let mut one = entry("https://example.org/checked.jpg", None);
+    assert_eq!(one.verify(&path), Ok(()));
+
+    one.size = Some(7);
+    one.sha256 =
+        Some("2B326696AD9ED964C517EB63F7BA2FDC731254E85B7BA70ECCA055F63CE861E6".to_owned());
+    one.md5 = Some("1ff07174d774ea8f9e5cdaa90ffbd118".to_owned());
+    assert_eq!(one.verify(&path), Ok(()));
+
+    one.md5 = Some("0".repeat(32));
+    assert!(one.verify(&path).unwrap_err().starts_with("md5"));
+
+    one.sha256 = Some("0".repeat(64));
+    assert!(one.verify(&path).unwrap_err().starts_with("sha256"));
+
+    one.size = Some(8);
+    assert_eq!(one.verify(&path), Err("size 7, expected 8".to_owned()));
+}
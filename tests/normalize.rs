@@ -0,0 +1,72 @@
+//! Typed values parsed from the raw strings of the pages.
+
+use datura::normalize::{parse_count, parse_minutes, parse_price, Price};
+
+fn price(cents: u64, currency: &str) -> Option<Price> {
+    Some(Price {
+        cents,
+        currency: Some(currency.to_owned()),
+    })
+}
+
+#[test]
+fn prices() {
+    for (s, expected) in [
+        ("39.00元", price(3900, "CNY")),
+        ("CNY 39.00", price(3900, "CNY")),
+        ("NT$320", price(32000, "TWD")),
+        ("NT$1,200", price(120000, "TWD")),
+        ("1,200円", price(120000, "JPY")),
+        ("1,234,567円", price(123456700, "JPY")),
+        ("$1,200.50", price(120050, "USD")),
+        ("12,50 €", price(1250, "EUR")),
+        ("$12.99", price(1299, "USD")),
+        ("¥1200", price(120000, "CNY")),
+        ("HK$ 88", price(8800, "HKD")),
+        (
+            "25",
+            Some(Price {
+                cents: 2500,
+                currency: None,
+            }),
+        ),
+        ("暂无", None),
+        ("", None),
+    ] {
+        assert_eq!(parse_price(s), expected, "{s}");
+    }
+}
+
+#[test]
+fn counts() {
+    for (s, expected) in [
+        ("(1432人评分)", Some(1432)),
+        ("98 个评分", Some(98)),
+        ("1,234 ratings", Some(1234)),
+        ("12,345,678人评分", Some(12345678)),
+        ("1.2万人评分", Some(12000)),
+        ("3萬", Some(30000)),
+        ("评价人数不足", None),
+    ] {
+        assert_eq!(parse_count(s), expected, "{s}");
+    }
+}
+
+#[test]
+fn minutes() {
+    for (s, expected) in [
+        ("120分钟", Some(120)),
+        ("120 分钟(中国大陆)", Some(120)),
+        ("120分钟(中国大陆) / 130分钟(美国)", Some(120)),
+        ("2小时", Some(120)),
+        ("1小时30分钟", Some(90)),
+        ("90 min", Some(90)),
+        ("1h 30m", Some(90)),
+        ("45分钟×12集", Some(45)),
+        ("每集45分钟 共12集", Some(45)),
+        ("0分钟", None),
+        ("未知", None),
+    ] {
+        assert_eq!(parse_minutes(s), expected, "{s}");
+    }
+}
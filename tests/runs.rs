@@ -0,0 +1,64 @@
+//! Run records in the `runs` tree.
+
+use bincode::config::standard;
+use datura::{
+    progress::Counts,
+    runs::{KindRun, Run},
+};
+
+fn counts(ok: u64) -> Counts {
+    Counts {
+        attempted: ok + 1,
+        ok,
+        failed: 1,
+        ..Counts::default()
+    }
+}
+
+#[test]
+fn one_run_per_crawl() {
+    let db = sled::Config::new().temporary(true).open().unwrap();
+    let run = Run {
+        mode: "full".to_owned(),
+        start: 10,
+        end: 20,
+        kinds: ["movie", "book", "album"]
+            .iter()
+            .map(|kind| KindRun {
+                kind: kind.to_string(),
+                items: counts(2),
+                covers: counts(1),
+            })
+            .collect(),
+    };
+    let id = run.save(&db);
+    let run = Run::get(&db, id).unwrap();
+    assert_eq!(run.kinds.len(), 3);
+    assert_eq!((run.items().attempted, run.items().ok), (9, 6));
+    assert_eq!(run.covers().ok, 3);
+    assert!(run.summary().contains("movie,book,album"));
+}
+
+#[test]
+fn bad_records_are_skipped() {
+    let db = sled::Config::new().temporary(true).open().unwrap();
+    let runs = db.open_tree("runs").unwrap();
+    runs.insert(1u64.to_be_bytes(), b"\xFF\x01junk".to_vec())
+        .unwrap();
+    // unversioned
+    let unversioned = bincode::encode_to_vec(("book", 10u64), standard()).unwrap();
+    runs.insert(2u64.to_be_bytes(), unversioned).unwrap();
+    let id = Run {
+        mode: "incremental".to_owned(),
+        start: 10,
+        end: 20,
+        kinds: vec![],
+    }
+    .save(&db);
+
+    let list = Run::list(&db);
+    assert_eq!(list.len(), 1);
+    assert_eq!((list[0].0, list[0].1.mode.as_str()), (id, "incremental"));
+    assert!(Run::get(&db, 1).is_none());
+    assert!(Run::get(&db, 2).is_none());
+}
@@ -0,0 +1,83 @@
+//! Tokens and ranking of the full-text search.
+
+mod common;
+
+use common::fixture;
+use datura::{
+    download::Trees,
+    extract::{Album, Book, Movie},
+    search::{index_doc, search, tokenize},
+};
+#[test]
+fn tokens() {
+    for (text, expected) in [
+        ("Flowers for Algernon", vec!["flowers", "for", "algernon"]),
+        (
+            "  ÉCOLE-normale, 2nd ed. ",
+            vec!["école", "normale", "2nd", "ed"],
+        ),
+        ("三体", vec!["三体"]),
+        ("三体问题", vec!["三体", "体问", "问题"]),
+        ("三", vec!["三"]),
+        ("三 体", vec!["三", "体"]),
+        ("三体II黑暗森林", vec!["三体", "ii", "黑暗", "暗森", "森林"]),
+        (
+            "ハリー・ポッター",
+            vec!["ハリ", "リー", "ポッ", "ッタ", "ター"],
+        ),
+        ("백년의 고독", vec!["백년", "년의", "고독"]),
+        ("《百年孤独》", vec!["百年", "年孤", "孤独"]),
+        ("", vec![]),
+        (" - / ", vec![]),
+    ] {
+        assert_eq!(tokenize(text), expected, "{text}");
+    }
+}
+
+#[test]
+fn ranking() {
+    let db = sled::Config::new().temporary(true).open().unwrap();
+    let books = Trees::open::<Book>(&db);
+    books.store(1, &fixture::<Book>("book", "full"));
+    books.store(2, &fixture::<Book>("book", "translated"));
+    Trees::open::<Movie>(&db).store(1, &fixture::<Movie>("movie", "film"));
+    Trees::open::<Album>(&db).store(1, &fixture::<Album>("album", "full"));
+
+    let hits = search(&db, "百年孤独", None, 10);
+    assert_eq!(hits.len(), 1);
+    assert_eq!((hits[0].kind, hits[0].id), ("book", 2));
+    assert_eq!(hits[0].title, "百年孤独");
+
+    // one character, found by the bigrams it starts
+    let hits = search(&db, "孤", None, 10);
+    assert_eq!(
+        hits.iter().map(|h| (h.kind, h.id)).collect::<Vec<_>>(),
+        [("book", 2)]
+    );
+    assert!(search(&db, "孤", Some("movie"), 10).is_empty());
+    assert!(search(&db, "zzzz", None, 10).is_empty());
+}
+
+#[test]
+fn limit_and_order() {
+    let db = sled::Config::new().temporary(true).open().unwrap();
+    let books = Trees::open::<Book>(&db);
+    let doc = |id: u32, fields: &[(&str, u32)]| index_doc(&books.fts, &books.fts_docs, id, fields);
+    for id in 1..=5 {
+        books.store(id, &fixture::<Book>("book", "full"));
+    }
+    // the indexed texts of the stored items, rarer and repeated tokens first
+    doc(1, &[("rust", 1)]);
+    doc(2, &[("rust rust rust", 1)]);
+    doc(3, &[("rust sled", 1)]);
+    doc(4, &[("sled", 1)]);
+    doc(5, &[("rust", 5)]);
+    // a posting of an item which is not stored is skipped, not counted in the limit
+    doc(6, &[("rust rust rust rust", 9)]);
+
+    let ids = |hits: Vec<datura::search::Hit>| hits.iter().map(|h| h.id).collect::<Vec<_>>();
+    assert_eq!(ids(search(&db, "rust", None, 10)), [5, 2, 1, 3]);
+    assert_eq!(ids(search(&db, "rust", None, 2)), [5, 2]);
+    assert_eq!(ids(search(&db, "sled rust", Some("book"), 1)), [3]);
+    assert!(search(&db, "rust", None, 0).is_empty());
+}
@@ -0,0 +1,140 @@
+//! Album track lists, durations and genres.
+
+use datura::{
+    normalize::{parse_duration, parse_genres},
+    tracks::{from_page, numbered, parse_track_list, Track},
+};
+
+fn track(disc: u32, number: u32, title: &str, duration: Option<u32>) -> Track {
+    Track {
+        disc,
+        number,
+        title: title.to_owned(),
+        duration,
+    }
+}
+
+#[test]
+fn track_lines() {
+    for (line, expected) in [
+        ("1. Airbag", Some(track(1, 1, "Airbag", None))),
+        ("1) Airbag", Some(track(1, 1, "Airbag", None))),
+        ("1、Airbag", Some(track(1, 1, "Airbag", None))),
+        ("1: Airbag", Some(track(1, 1, "Airbag", None))),
+        (
+            "12 - Karma Police",
+            Some(track(1, 12, "Karma Police", None)),
+        ),
+        ("01 Airbag", Some(track(1, 1, "Airbag", None))),
+        ("2-03 Nude (4:15)", Some(track(2, 3, "Nude", Some(255)))),
+        (
+            "1-01. In the Flesh? (3:20)",
+            Some(track(1, 1, "In the Flesh?", Some(200))),
+        ),
+        ("3. Let Down 4:59", Some(track(1, 3, "Let Down", Some(299)))),
+        ("A1 Speak to Me", Some(track(1, 1, "Speak to Me", None))),
+        ("4. 1984 (Live)", Some(track(1, 4, "1984 (Live)", None))),
+        // numbers in titles
+        ("1984 (Live)", None),
+        ("99 Luftballons", None),
+        ("2.03 Nude", None),
+        ("15 Step", None),
+        ("100. Airbag", None),
+        ("0. Airbag", None),
+        ("0-01 Airbag", None),
+        ("4:44", None),
+        ("MK 1", None),
+        ("Z1 Airbag", None),
+        ("1.", None),
+    ] {
+        assert_eq!(parse_track_list(line).pop(), expected, "{line}");
+    }
+}
+
+#[test]
+fn discs() {
+    let list = "CD 1\n1. Airbag\n2. Paranoid Android\n\nDisc 2:\n1. Hey You\n碟3\n1. Nude";
+    assert_eq!(
+        parse_track_list(list),
+        [
+            track(1, 1, "Airbag", None),
+            track(1, 2, "Paranoid Android", None),
+            track(2, 1, "Hey You", None),
+            track(3, 1, "Nude", None),
+        ]
+    );
+
+    // vinyl sides are numbered in order
+    let list = "A1 Speak to Me\nA2 Breathe (2:43)\nB1 Money";
+    assert_eq!(
+        parse_track_list(list),
+        [
+            track(1, 1, "Speak to Me", None),
+            track(1, 2, "Breathe", Some(163)),
+            track(1, 3, "Money", None),
+        ]
+    );
+}
+
+#[test]
+fn listed_or_elements() {
+    let elements = numbered(vec![vec![
+        ("Airbag".to_owned(), None),
+        ("Paranoid Android".to_owned(), None),
+    ]]);
+
+    let list = "1. Airbag (4:44)\n2. Paranoid Android (6:23)";
+    assert_eq!(
+        from_page(Some(list), elements.clone()),
+        [
+            track(1, 1, "Airbag", Some(284)),
+            track(1, 2, "Paranoid Android", Some(383)),
+        ]
+    );
+    // not the same tracks
+    let list = "1. Airbag (4:44)";
+    assert_eq!(from_page(Some(list), elements.clone()), elements);
+    assert_eq!(from_page(None, elements.clone()), elements);
+
+    // without elements, only a list numbered in order
+    let list = "1. Airbag\n2. Paranoid Android\nDisc 2\n1. Lucky";
+    assert_eq!(from_page(Some(list), vec![]).len(), 3);
+    let list = "Recorded 1997\n3. Subterranean Homesick Alien\n7. Karma Police";
+    assert_eq!(from_page(Some(list), vec![]), []);
+    assert_eq!(from_page(None, vec![]), []);
+}
+
+#[test]
+fn durations() {
+    for (s, expected) in [
+        ("4:23", Some(263)),
+        ("1:02:03", Some(3723)),
+        ("(3:21)", Some(201)),
+        ("[0:59]", Some(59)),
+        (" 12:00 ", Some(720)),
+        ("0:00", None),
+        ("4:60", None),
+        ("1:60:00", None),
+        ("4.23", None),
+        ("4:", None),
+        ("", None),
+    ] {
+        assert_eq!(parse_duration(s), expected, "{s}");
+    }
+}
+
+#[test]
+fn genres() {
+    for (s, expected) in [
+        ("摇滚 / Pop", vec!["Rock", "Pop"]),
+        ("搖滾", vec!["Rock"]),
+        ("rock, 流行; POP", vec!["Rock", "Pop"]),
+        ("放克/灵魂/r&b", vec!["Funk / Soul"]),
+        ("Funk / Soul", vec!["Funk / Soul"]),
+        ("雷鬼、R&B", vec!["Reggae", "R&B"]),
+        ("Shoegaze | rock", vec!["Shoegaze", "Rock"]),
+        (" / ", vec![]),
+    ] {
+        assert_eq!(parse_genres(s), expected, "{s}");
+    }
+}
@@ -0,0 +1,126 @@
+//! Consistency checks of `verify`, and what `--repair` removes or leaves alone.
+
+mod common;
+
+use common::{fixture, temp_dir};
+use datura::{
+    download::{index_key, u32_to_ivec, Trees},
+    extract::Book,
+    runs::Run,
+    schema::Versioned,
+    verify::{verify, verify_runs, Issue},
+};
+use std::fs;
+
+/// names of the issues, sorted
+fn names(issues: &[Issue]) -> Vec<&'static str> {
+    let mut names: Vec<_> = issues
+        .iter()
+        .map(|issue| match issue {
+            Issue::Undecodable { .. } => "undecodable",
+            Issue::NewerVersion { .. } => "newer",
+            Issue::BadKey { .. } => "bad key",
+            Issue::MissingCover { .. } => "missing cover",
+            Issue::OrphanCover { .. } => "orphan cover",
+            Issue::StoredAndNotFound { .. } => "stored and 404",
+            Issue::DanglingIndex { .. } => "dangling index",
+        })
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn newer_items_are_kept() {
+    let db = sled::Config::new().temporary(true).open().unwrap();
+    let dir = temp_dir("verify-newer");
+    let covers = dir.to_str().unwrap();
+    let trees = Trees::open::<Book>(&db);
+    trees.store(1, &fixture::<Book>("book", "full"));
+    let newer = vec![0xFF, Book::VERSION + 1, 1, 2, 3];
+    trees.data.insert(u32_to_ivec(2), newer.clone()).unwrap();
+    trees.data.insert(u32_to_ivec(3), b"junk".to_vec()).unwrap();
+
+    let issues = verify::<Book>(&db, covers, false);
+    assert_eq!(names(&issues), ["newer", "undecodable"]);
+    assert!(matches!(
+        &issues[..],
+        [Issue::NewerVersion { version, .. }, _] if *version == Book::VERSION + 1
+    ));
+
+    let issues = verify::<Book>(&db, covers, true);
+    assert_eq!(names(&issues), ["newer", "undecodable"]);
+    assert_eq!(trees.data.get(u32_to_ivec(2)).unwrap().unwrap(), newer);
+    assert!(!trees.data.contains_key(u32_to_ivec(3)).unwrap());
+    assert!(trees.data.contains_key(u32_to_ivec(1)).unwrap());
+
+    assert_eq!(names(&verify::<Book>(&db, covers, true)), ["newer"]);
+}
+
+#[test]
+fn repair() {
+    let db = sled::Config::new().temporary(true).open().unwrap();
+    let dir = temp_dir("verify-repair");
+    let covers = dir.to_str().unwrap();
+    let trees = Trees::open::<Book>(&db);
+    trees.store(1, &fixture::<Book>("book", "full"));
+    trees.store(2, &fixture::<Book>("book", "translated"));
+    // 1 has its cover, 2 lost it, 7 was never recorded
+    fs::write(dir.join("1.jpg"), "cover 1").unwrap();
+    fs::write(dir.join("7.jpg"), "cover 7").unwrap();
+    fs::write(dir.join("8.jpg.part"), "cov").unwrap();
+    trees.covers.insert(u32_to_ivec(1), vec![]).unwrap();
+    trees.covers.insert(u32_to_ivec(2), vec![]).unwrap();
+    trees.not_found.insert(u32_to_ivec(2), vec![]).unwrap();
+    trees.not_found.insert(vec![1, 2], vec![]).unwrap();
+    trees.indexes[0]
+        .insert(index_key("9780000000002", 9), vec![])
+        .unwrap();
+    trees
+        .etags
+        .insert(u32_to_ivec(1), b"junk".to_vec())
+        .unwrap();
+
+    let issues = verify::<Book>(&db, covers, false);
+    assert_eq!(
+        names(&issues),
+        [
+            "bad key",
+            "dangling index",
+            "missing cover",
+            "orphan cover",
+            "stored and 404",
+            "undecodable"
+        ]
+    );
+    assert_eq!(verify::<Book>(&db, covers, true).len(), 6);
+
+    // the orphan file stays, but is the only issue left
+    assert_eq!(names(&verify::<Book>(&db, covers, false)), ["orphan cover"]);
+    assert!(dir.join("7.jpg").exists());
+    assert!(!trees.covers.contains_key(u32_to_ivec(2)).unwrap());
+    assert!(!trees.not_found.contains_key(u32_to_ivec(2)).unwrap());
+    assert_eq!(trees.indexes[0].len(), 2);
+    assert_eq!(trees.data.len(), 2);
+}
+
+#[test]
+fn runs() {
+    let db = sled::Config::new().temporary(true).open().unwrap();
+    let run = Run {
+        mode: "full".to_owned(),
+        start: 1,
+        end: 2,
+        kinds: vec![],
+    };
+    run.save(&db);
+    let tree = db.open_tree("runs").unwrap();
+    tree.insert(10u64.to_be_bytes(), vec![0xFF, Run::VERSION + 1])
+        .unwrap();
+    tree.insert(11u64.to_be_bytes(), vec![0xFF, Run::VERSION, 0xFF])
+        .unwrap();
+
+    assert_eq!(names(&verify_runs(&db, true)), ["newer", "undecodable"]);
+    assert_eq!(names(&verify_runs(&db, true)), ["newer"]);
+    assert_eq!(tree.len(), 2);
+}